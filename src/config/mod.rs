@@ -0,0 +1,251 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::keymap::{self, Action, Keymap};
+use crate::screen::key::Key;
+use crate::theme::{self, Theme};
+
+// 設定ファイル
+//
+//     # コメント
+//     keymap = vim
+//     bind ctrl-z = back
+//     unbind q
+//     theme = emoji
+//
+// keymapで組み込みの配置を選び、bindとunbindで書き換える
+// 組み込みの配置でほかの操作に使っているキーをbindで置き換えると警告する
+// themeでマスの表示を選ぶ
+#[derive(Debug, PartialEq)]
+pub struct Config {
+    keymap: String,
+    // 設定ファイルでkeymapを選んだ行番号。--keymapで変えたらNone
+    keymap_line: Option<usize>,
+    // (キー, 操作, 行番号)。Noneは割り当てを外す
+    bindings: Vec<(Key, Option<Action>, usize)>,
+    theme: String,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            keymap: keymap::DEFAULT_PRESET.to_string(),
+            keymap_line: None,
+            bindings: Vec::new(),
+            theme: theme::DEFAULT_THEME.to_string(),
+        }
+    }
+
+    // $XDG_CONFIG_HOME/mine_sweeper/config か ~/.config/mine_sweeper/config
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config")
+        };
+        Some(dir.join("mine_sweeper").join("config"))
+    }
+
+    pub fn load(path: &PathBuf) -> Result<Config, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::from_str(&text)
+                .map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) => Err(format!("{}: {}", path.display(), e))
+        }
+    }
+
+    pub fn set_keymap(&mut self, name: &str) {
+        self.keymap = name.to_string();
+        self.keymap_line = None;
+    }
+
+    pub fn set_theme(&mut self, name: &str) {
+        self.theme = name.to_string();
+    }
+
+    pub fn get_theme(&self) -> Result<Theme, String> {
+        Theme::preset(&self.theme)
+    }
+
+    // 選んだ組み込みの配置
+    // --keymapで変えられるので、名前は読み込んだ後で確かめる
+    fn get_preset(&self) -> Result<Keymap, String> {
+        Keymap::preset(&self.keymap).map_err(|e| match self.keymap_line {
+            Some(line_number) => format!("line {}: {}", line_number, e),
+            None => e
+        })
+    }
+
+    // 組み込みの配置に設定ファイルの割り当てを重ねたもの
+    pub fn get_keymap(&self) -> Result<Keymap, String> {
+        let mut keymap = self.get_preset()?;
+        for &(key, action, _) in &self.bindings {
+            match action {
+                Some(action) => keymap.bind(key, action),
+                None => keymap.unbind(key)
+            }
+        }
+        Ok(keymap)
+    }
+
+    // 組み込みの配置でほかの操作に使っているキーをbindで置き換えていれば、その警告
+    // 組み込みの配置は--keymapでも変わるので、読み込んだ後で確かめる
+    pub fn get_keymap_warnings(&self) -> Result<Vec<String>, String> {
+        let preset = self.get_preset()?;
+        let warnings = self.bindings.iter()
+            .filter_map(|&(key, action, line_number)| match (action, preset.get_action(key)) {
+                (Some(action), Some(shadowed)) if action != shadowed => {
+                    Some(format!("line {}: key '{}' replaces {} in the {} keymap",
+                                 line_number, key, shadowed, self.keymap))
+                },
+                _ => None
+            })
+            .collect();
+        Ok(warnings)
+    }
+}
+
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Config, String> {
+        let mut config = Config::new();
+        // 同じキーを別の操作に割り当てていないか確かめるための(操作, 行番号)
+        let mut bound_at: HashMap<Key, (Option<Action>, usize)> = HashMap::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, action) = if let Some(rest) = line.strip_prefix("bind ") {
+                // キーが"="のこともあるので最後の"="で分ける
+                let mut parts = rest.rsplitn(2, '=');
+                let action = parts.next().unwrap().trim();
+                let key = match parts.next() {
+                    Some(key) => key.trim(),
+                    None => return Err(format!("line {}: expected 'bind <key> = <action>'",
+                                               line_number))
+                };
+                (key, Some(Action::from_str(action)
+                    .map_err(|e| format!("line {}: {}", line_number, e))?))
+            } else if let Some(key) = line.strip_prefix("unbind ") {
+                (key.trim(), None)
+            } else if let Some(rest) = line.strip_prefix("keymap") {
+                match rest.trim_start().strip_prefix('=') {
+                    Some(name) => {
+                        config.keymap = name.trim().to_string();
+                        config.keymap_line = Some(line_number);
+                        continue;
+                    },
+                    None => return Err(format!("line {}: expected 'keymap = <name>'",
+                                               line_number))
+                }
+            } else if let Some(rest) = line.strip_prefix("theme") {
+                match rest.trim_start().strip_prefix('=') {
+                    Some(name) => {
+                        config.theme = name.trim().to_string();
+                        continue;
+                    },
+                    None => return Err(format!("line {}: expected 'theme = <name>'",
+                                               line_number))
+                }
+            } else {
+                return Err(format!("line {}: unknown setting '{}'", line_number, line));
+            };
+
+            let key = Key::from_str(key).map_err(|e| format!("line {}: {}", line_number, e))?;
+            if let Some(&(bound, bound_line)) = bound_at.get(&key) {
+                if bound != action {
+                    let bound = match bound {
+                        Some(action) => action.to_string(),
+                        None => "nothing (unbind)".to_string()
+                    };
+                    return Err(format!("line {}: key '{}' is already bound to {} on line {}",
+                                       line_number, key, bound, bound_line));
+                }
+            }
+            bound_at.insert(key, (action, line_number));
+            config.bindings.push((key, action, line_number));
+        }
+
+        // テーマの名前はここで確かめておく
+        Theme::preset(&config.theme)?;
+        Ok(config)
+    }
+}
+
+#[test]
+fn test_config_from_str() {
+    let config = Config::from_str("").unwrap();
+    assert_eq!(config, Config::new());
+    assert_eq!(config.get_keymap(), Keymap::preset(keymap::DEFAULT_PRESET));
+    assert_eq!(config.get_theme(), Ok(Theme::default()));
+
+    let config = Config::from_str("# vim with undo on z\n\
+                                   keymap = vim\n\
+                                   \n\
+                                   bind z = back\n\
+                                   bind = = open\n\
+                                   unbind u\n\
+                                   bind z = back\n\
+                                   theme = classic\n").unwrap();
+    assert_eq!(config.get_theme(), Theme::preset("classic"));
+    let keymap = config.get_keymap().unwrap();
+    assert_eq!(keymap.get_action(Key::char('z')), Some(Action::Back));
+    assert_eq!(keymap.get_action(Key::char('=')), Some(Action::Open));
+    assert_eq!(keymap.get_action(Key::char('u')), None);
+    assert_eq!(keymap.get_action(Key::char('h')), Some(Action::CursorLeft));
+    assert_eq!(config.get_keymap_warnings(), Ok(vec![]));
+}
+#[test]
+fn test_config_keymap_warnings() {
+    // 組み込みの配置の操作を置き換えるbindは警告する
+    let mut config = Config::from_str("keymap = vim
+                                       bind h = back
+                                       bind l = cursor-right
+                                       unbind j
+").unwrap();
+    assert_eq!(config.get_keymap_warnings(),
+               Ok(vec!["line 2: key 'h' replaces cursor-left in the vim keymap".to_string()]));
+    assert_eq!(config.get_keymap().unwrap().get_action(Key::char('h')), Some(Action::Back));
+    // --keymapで変えた配置と比べる
+    config.set_keymap("wasd");
+    assert_eq!(config.get_keymap_warnings(),
+               Ok(vec!["line 3: key 'l' replaces list-history in the wasd keymap".to_string()]));
+}
+#[test]
+fn test_config_errors() {
+    assert_eq!(Config::from_str("bind z = back\nbind z = open"),
+               Err("line 2: key 'z' is already bound to back on line 1".to_string()));
+    assert_eq!(Config::from_str("unbind z\n\nbind z = open"),
+               Err("line 3: key 'z' is already bound to nothing (unbind) on line 1".to_string()));
+    assert_eq!(Config::from_str("bind z = fly"),
+               Err("line 1: unknown action 'fly'".to_string()));
+    assert_eq!(Config::from_str("bind foo = open"),
+               Err("line 1: unknown key 'foo'".to_string()));
+    assert_eq!(Config::from_str("bind z"),
+               Err("line 1: expected 'bind <key> = <action>'".to_string()));
+    assert_eq!(Config::from_str("colour = red"),
+               Err("line 1: unknown setting 'colour = red'".to_string()));
+    // 配置の名前は--keymapで変えられるので、使うときに確かめる
+    let mut config = Config::from_str("bind z = back\nkeymap = dvorak").unwrap();
+    assert_eq!(config.get_keymap(),
+               Err("line 2: unknown keymap 'dvorak' (available: colemak, vim, wasd, arrows)"
+                   .to_string()));
+    assert!(config.get_keymap_warnings().is_err());
+    config.set_keymap("vim");
+    assert!(config.get_keymap().is_ok());
+    config.set_keymap("qwerty");
+    assert_eq!(config.get_keymap(),
+               Err("unknown keymap 'qwerty' (available: colemak, vim, wasd, arrows)".to_string()));
+    assert!(Config::from_str("theme = neon").is_err());
+    assert_eq!(Config::from_str("theme emoji"),
+               Err("line 1: expected 'theme = <name>'".to_string()));
+}
@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
 use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::game::coordinate;
+use crate::theme::{Theme, GLYPH_WIDTH};
 
 #[derive(Debug, PartialEq, Clone)]
 struct Position {
@@ -18,7 +23,7 @@ pub struct Square {
     pos: Position,
 }
 
-
+#[cfg(test)]
 use std::mem;
 
 #[test]
@@ -26,22 +31,6 @@ fn test_square() {
     assert_eq!(mem::size_of::<Square>(), 80);
 }
 
-fn number_to_zenkaku_string(number: usize) -> String {
-    match number {
-        0 => "０".to_string(),
-        1 => "１".to_string(),
-        2 => "２".to_string(),
-        3 => "３".to_string(),
-        4 => "４".to_string(),
-        5 => "５".to_string(),
-        6 => "６".to_string(),
-        7 => "７".to_string(),
-        8 => "８".to_string(),
-        9 => "９".to_string(),
-        _ => "０".to_string(),
-    }
-}
-
 impl Square {
     fn new(x: usize, y: usize) -> Square {
         Square {
@@ -56,42 +45,81 @@ impl Square {
         }
     }
 
-    fn to_string(&self) -> String {
-        if *self.is_open.borrow() == true {
-            if *self.is_mine.borrow() == true {
-                return "\x1b[91m<>\x1b[0m".to_string();
-            }
-            if *self.number.borrow() == 0 {
-                return "  ".to_string();
+    // (x, y)
+    pub fn get_pos(&self) -> (usize, usize) {
+        (self.pos.x, self.pos.y)
+    }
+}
+
+impl Square {
+    // テーマのグリフで表す
+    pub fn to_string_with_theme(&self, theme: &Theme) -> String {
+        let glyph = if *self.is_open.borrow() {
+            if *self.is_mine.borrow() {
+                theme.get_mine()
+            } else {
+                theme.get_number(*self.number.borrow())
             }
-            //return self.number.borrow().to_string() +
-            //       &self.number.borrow().to_string()
-            return  number_to_zenkaku_string(*self.number.borrow())
-        }
-        if *self.is_flag.borrow() == true {
-            return "\x1b[93m/>\x1b[0m".to_string();
-        }
-        "[]".to_string()
+        } else if *self.is_flag.borrow() {
+            theme.get_flag()
+        } else {
+            theme.get_unopened()
+        };
+        glyph.to_string()
     }
 }
 
 #[test]
 fn test_square_to_string() {
+    let theme = Theme::default();
     let square = Square::new(0, 0);
-    assert_eq!(square.to_string().as_str(), "[]");
+    assert_eq!(square.to_string_with_theme(&theme).as_str(), "[]");
     *square.is_flag.borrow_mut() = true;
-    assert_eq!(square.to_string().as_str(), "\x1b[93m/>\x1b[0m");
+    assert_eq!(square.to_string_with_theme(&theme).as_str(), "\x1b[93m/>\x1b[0m");
     *square.is_open.borrow_mut() = true;
-    assert_eq!(square.to_string().as_str(), "  ");
+    assert_eq!(square.to_string_with_theme(&theme).as_str(), "  ");
     *square.number.borrow_mut() = 1;
-    assert_eq!(square.to_string().as_str(), "１");
+    assert_eq!(square.to_string_with_theme(&theme).as_str(), "１");
     *square.number.borrow_mut() = 8;
-    assert_eq!(square.to_string().as_str(), "８");
+    assert_eq!(square.to_string_with_theme(&theme).as_str(), "８");
     *square.is_mine.borrow_mut() = true;
-    assert_eq!(square.to_string().as_str(), "\x1b[91m<>\x1b[0m");
+    assert_eq!(square.to_string_with_theme(&theme).as_str(), "\x1b[91m<>\x1b[0m");
 }
 
 
+// 履歴の木のノード
+// 0番目のノードは最初の状態を表し、マスを持たない
+#[derive(Debug, PartialEq)]
+struct History {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // 最後に進んだ子。進めるときはこの子に進む
+    last_child: Option<usize>,
+    // このノードの変更が盤面に反映されているときは変更前のマス
+    // 反映されていないときは変更後のマス
+    squares: Vec<Square>,
+}
+
+impl History {
+    fn new(parent: Option<usize>, squares: Vec<Square>) -> History {
+        History {
+            parent,
+            children: Vec::new(),
+            last_child: None,
+            squares,
+        }
+    }
+}
+
+// 履歴の枝。葉のノードごとに１つある
+#[derive(Debug, PartialEq, Clone)]
+pub struct Branch {
+    // 最初の状態からの手数
+    pub moves: usize,
+    // 進めたときにたどる枝ならtrue
+    pub is_current: bool,
+}
+
 // マスを持つクラス
 #[derive(Debug, PartialEq)]
 pub struct Board {
@@ -99,8 +127,14 @@ pub struct Board {
     size: (usize, usize),
     // マスの２次元配列
     squares: Vec<Vec<Square>>,
-    // 過去のsquaresの配列
-    squares_history: Vec<Vec<Square>>
+    // 過去のsquaresの木。戻してから別の変更をすると枝分かれする
+    squares_history: Vec<History>,
+    // 今の盤面の状態を表すノード
+    current_history: usize,
+    // 名前をつけたノード
+    checkpoints: BTreeMap<String, usize>,
+    // マスを文字列にするときのグリフ
+    theme: Theme,
 }
 
 impl Board {
@@ -112,7 +146,10 @@ impl Board {
         let mut board = Board {
             size: (y, x),
             squares: Vec::new(),
-            squares_history: Vec::new(),
+            squares_history: vec![History::new(None, Vec::new())],
+            current_history: 0,
+            checkpoints: BTreeMap::new(),
+            theme: Theme::default(),
         };
 
         for h in 0..y {
@@ -122,7 +159,15 @@ impl Board {
             }
         }
 
-        return Ok(board);
+        Ok(board)
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    pub fn get_theme(&self) -> &Theme {
+        &self.theme
     }
 
     pub fn get_size(&self) -> (usize, usize) {
@@ -130,16 +175,7 @@ impl Board {
     }
 
     pub fn get_square(&self, pos: (usize, usize)) -> Option<&Square> {
-        let line = match self.squares.get(pos.1) {
-            Some(line) => line,
-            None => return None
-        };
-        let square = match line.get(pos.0) {
-            Some(square) => square,
-            None => return None
-        };
-
-        Some(square)
+        self.squares.get(pos.1)?.get(pos.0)
     }
 
     // 上下左右
@@ -175,42 +211,170 @@ impl Board {
     }
 
     pub fn get_around_squares_of(&self, square: &Square) -> Vec<(String, Option<&Square>)> {
-        let mut squares = Vec::new();
-        squares.push(("right".to_string(), self.get_right_square_of(square)));
-        squares.push(("left".to_string(), self.get_left_square_of(square)));
-        squares.push(("upper".to_string(), self.get_upper_square_of(square)));
-        squares.push(("lower".to_string(), self.get_lower_square_of(square)));
-
-        squares.push(("upper_right".to_string(), self.get_upper_right_square_of(square)));
-        squares.push(("lower_right".to_string(), self.get_lower_right_square_of(square)));
-        squares.push(("upper_left".to_string(), self.get_upper_left_square_of(square)));
-        squares.push(("lower_left".to_string(), self.get_lower_left_square_of(square)));
+        vec![
+            ("right".to_string(), self.get_right_square_of(square)),
+            ("left".to_string(), self.get_left_square_of(square)),
+            ("upper".to_string(), self.get_upper_square_of(square)),
+            ("lower".to_string(), self.get_lower_square_of(square)),
 
-        squares
+            ("upper_right".to_string(), self.get_upper_right_square_of(square)),
+            ("lower_right".to_string(), self.get_lower_right_square_of(square)),
+            ("upper_left".to_string(), self.get_upper_left_square_of(square)),
+            ("lower_left".to_string(), self.get_lower_left_square_of(square)),
+        ]
     }
 
     pub fn add_squares_history(&mut self, squares: Vec<Square>) {
-        self.squares_history.push(squares);
+        let node = self.squares_history.len();
+        self.squares_history.push(History::new(Some(self.current_history), squares));
+
+        let current = &mut self.squares_history[self.current_history];
+        current.children.push(node);
+        current.last_child = Some(node);
+        self.current_history = node;
     }
 
     pub fn back_squares_history(&mut self) {
-        if self.squares_history.len() == 0 {
-            return;
+        let parent = match self.squares_history[self.current_history].parent {
+            Some(parent) => parent,
+            None => return
+        };
+
+        self.swap_history_squares(self.current_history);
+        self.current_history = parent;
+    }
+
+    pub fn forward_squares_history(&mut self) {
+        let child = match self.squares_history[self.current_history].last_child {
+            Some(child) => child,
+            None => return
+        };
+
+        self.swap_history_squares(child);
+        self.current_history = child;
+    }
+
+    // 盤面とノードのマスを入れ替える
+    fn swap_history_squares(&mut self, node: usize) {
+        let squares = std::mem::take(&mut self.squares_history[node].squares);
+        self.squares_history[node].squares = self.replace_squares(squares);
+    }
+
+    // 最初の状態からの手数
+    fn depth_of_history(&self, mut node: usize) -> usize {
+        let mut depth = 0;
+        while let Some(parent) = self.squares_history[node].parent {
+            node = parent;
+            depth += 1;
+        }
+        depth
+    }
+
+    // 共通の祖先まで戻ってから、targetまで進める
+    fn jump_to_history(&mut self, target: usize) {
+        let mut path = Vec::new();
+        let mut node = Some(target);
+        while let Some(n) = node {
+            path.push(n);
+            node = self.squares_history[n].parent;
+        }
+
+        while !path.contains(&self.current_history) {
+            self.back_squares_history();
         }
-        let squares = self.squares_history.pop().unwrap();
 
+        let index = path.iter().position(|&n| n == self.current_history).unwrap();
+        for &node in path[..index].iter().rev() {
+            let parent = self.squares_history[node].parent.unwrap();
+            self.squares_history[parent].last_child = Some(node);
+            self.forward_squares_history();
+        }
+    }
+
+    // 今の状態に名前をつける。同じ名前があれば上書きする
+    pub fn add_checkpoint(&mut self, name: &str) {
+        self.checkpoints.insert(name.to_string(), self.current_history);
+    }
+
+    // (名前, 手数)
+    pub fn get_checkpoints(&self) -> Vec<(String, usize)> {
+        self.checkpoints.iter()
+            .map(|(name, &node)| (name.clone(), self.depth_of_history(node)))
+            .collect()
+    }
+
+    pub fn jump_to_checkpoint(&mut self, name: &str) -> Result<(), String> {
+        let node = match self.checkpoints.get(name) {
+            Some(&node) => node,
+            None => return Err(format!("checkpoint {} is not found", name))
+        };
+
+        self.jump_to_history(node);
+        Ok(())
+    }
+
+    // 葉のノード
+    fn get_leaf_histories(&self) -> Vec<usize> {
+        (0..self.squares_history.len())
+            .filter(|&node| self.squares_history[node].children.is_empty())
+            .collect()
+    }
+
+    // 作られた順に並んだ枝
+    pub fn get_branches(&self) -> Vec<Branch> {
+        let mut current_leaf = self.current_history;
+        while let Some(child) = self.squares_history[current_leaf].last_child {
+            current_leaf = child;
+        }
+
+        self.get_leaf_histories().into_iter()
+            .map(|node| Branch {
+                moves: self.depth_of_history(node),
+                is_current: node == current_leaf,
+            })
+            .collect()
+    }
+
+    // index番目の枝の最後の状態に移る
+    pub fn jump_to_branch(&mut self, index: usize) -> Result<(), String> {
+        let node = match self.get_leaf_histories().get(index) {
+            Some(&node) => node,
+            None => return Err(format!("branch {} is not found", index))
+        };
+
+        self.jump_to_history(node);
+        Ok(())
+    }
+
+    // squaresのマスで置き換えて、置き換える前のマスを返す
+    fn replace_squares(&mut self, squares: Vec<Square>) -> Vec<Square> {
+        let mut current_squares = Vec::with_capacity(squares.len());
         for square in squares {
             let x = square.pos.x;
             let y = square.pos.y;
-            self.squares[y][x] = square;
+            current_squares.push(std::mem::replace(&mut self.squares[y][x], square));
         }
+        current_squares
+    }
+
+    // カーソルの部分は色が反転する
+    pub fn to_string_with_cursor(&self, cursor: (usize, usize)) -> String {
+        self.to_string_in_window(cursor, (0, 0), (self.size.1, self.size.0))
     }
 
-    pub fn to_string(&self) -> String {
+    // originのマス(x, y)から(列数, 行数)がsizeの範囲だけを文字列にする
+    pub fn to_string_in_window(&self, cursor: (usize, usize),
+                               origin: (usize, usize), size: (usize, usize)) -> String {
         let mut board_string = String::new();
-        for (y, line) in self.squares.iter().enumerate() {
-            for (x, square) in line.iter().enumerate() {
-                board_string += &square.to_string();
+        for (y, line) in self.squares.iter().enumerate().skip(origin.1).take(size.1) {
+            for (x, square) in line.iter().enumerate().skip(origin.0).take(size.0) {
+                if cursor == (x, y) {
+                    board_string += "\x1b[7m";
+                    board_string += &square.to_string_with_theme(&self.theme);
+                    board_string += "\x1b[27m";
+                } else {
+                    board_string += &square.to_string_with_theme(&self.theme);
+                }
             }
             board_string += "\n";
         }
@@ -218,25 +382,59 @@ impl Board {
         board_string.trim_end_matches("\n").to_string()
     }
 
-    // カーソルの部分は色が反転する
-    pub fn to_string_with_cursor(&self, cursor: (usize, usize)) -> String {
+    // 上に列の名前を、左に行の番号を付けたto_string_in_window
+    // 列の名前は１文字ずつ縦に並べ、下の端をそろえる
+    //
+    //                       A A
+    //       A B C ... X Y Z A B
+    //     9 [][][]
+    //    10 []２[]
+    pub fn to_string_with_rulers(&self, cursor: (usize, usize),
+                                 origin: (usize, usize), size: (usize, usize)) -> String {
+        let (left, rows) = self.get_ruler_size();
+        let names: Vec<String> = (origin.0..self.size.1).take(size.0)
+            .map(coordinate::column_name)
+            .collect();
+
+        let mut lines = Vec::new();
+        for row in 0..rows {
+            let mut columns = " ".repeat(left);
+            for name in &names {
+                // 短い名前は上を空ける
+                let c = (row + name.len()).checked_sub(rows).and_then(|i| name.chars().nth(i));
+                columns += &format!("{:<w$}", c.unwrap_or(' '), w = GLYPH_WIDTH);
+            }
+            lines.push(columns.trim_end().to_string());
+        }
+        let board = self.to_string_in_window(cursor, origin, size);
+        for (y, line) in board.lines().enumerate() {
+            lines.push(format!("{:>w$} {}", coordinate::row_name(origin.1 + y), line, w = left - 1));
+        }
+        lines.join("\n")
+    }
+
+    // to_string_with_rulersで付ける名前と番号の(列数, 行数)
+    // 番号の幅は一番下の行に、名前の行数は一番右の列に合わせるので、表示する範囲によらない
+    pub fn get_ruler_size(&self) -> (usize, usize) {
+        (coordinate::row_name(self.size.0.saturating_sub(1)).len() + 1,
+         coordinate::column_name(self.size.1.saturating_sub(1)).len())
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut board_string = String::new();
-        for (y, line) in self.squares.iter().enumerate() {
-            for (x, square) in line.iter().enumerate() {
-                if cursor == (x, y) {
-                    board_string += &"\x1b[7m";
-                    board_string += &square.to_string();
-                    board_string += &"\x1b[27m";
-                } else {
-                    board_string += &square.to_string();
-                }
+        for line in &self.squares {
+            for square in line {
+                board_string += &square.to_string_with_theme(&self.theme);
             }
             board_string += "\n";
         }
 
-        board_string.trim_end_matches("\n").to_string()
+        write!(f, "{}", board_string.trim_end_matches('\n'))
     }
 }
+
 #[test]
 fn test_board_new() {
     assert_eq!(Board::new(0, 0), Err("x or y is 0.".to_string()));
@@ -301,7 +499,7 @@ fn test_board_get_around_squares_of() {
     let board = Board::new(3, 3).unwrap();
     let square = board.get_square((1, 1)).unwrap();
     let around_squares = board.get_around_squares_of(square);
-    for (i, (direction, around_square)) in around_squares.iter().enumerate() {
+    for (direction, around_square) in around_squares.iter() {
         let number = match direction.as_str() {
             "right" => 1,
             "left" => 2,
@@ -322,6 +520,90 @@ fn test_board_get_around_squares_of() {
                 ８４６");
 }
 #[test]
+fn test_board_squares_history() {
+    let mut board = Board::new(2, 1).unwrap();
+    let before_squares = vec![board.get_square((0, 0)).unwrap().clone()];
+    *board.get_square((0, 0)).unwrap().is_open.borrow_mut() = true;
+    board.add_squares_history(before_squares);
+    let before_squares = vec![board.get_square((1, 0)).unwrap().clone()];
+    *board.get_square((1, 0)).unwrap().is_flag.borrow_mut() = true;
+    board.add_squares_history(before_squares);
+    assert_eq!(board.to_string().as_str(), "  \x1b[93m/>\x1b[0m");
+
+    board.back_squares_history();
+    assert_eq!(board.to_string().as_str(), "  []");
+    board.back_squares_history();
+    assert_eq!(board.to_string().as_str(), "[][]");
+    board.back_squares_history();
+    assert_eq!(board.to_string().as_str(), "[][]");
+    board.forward_squares_history();
+    assert_eq!(board.to_string().as_str(), "  []");
+    board.forward_squares_history();
+    assert_eq!(board.to_string().as_str(), "  \x1b[93m/>\x1b[0m");
+    board.forward_squares_history();
+    assert_eq!(board.to_string().as_str(), "  \x1b[93m/>\x1b[0m");
+
+    // 新しく変更したら進められない
+    board.back_squares_history();
+    let before_squares = vec![board.get_square((1, 0)).unwrap().clone()];
+    *board.get_square((1, 0)).unwrap().is_open.borrow_mut() = true;
+    board.add_squares_history(before_squares);
+    board.forward_squares_history();
+    assert_eq!(board.to_string().as_str(), "    ");
+    board.back_squares_history();
+    assert_eq!(board.to_string().as_str(), "  []");
+}
+#[cfg(test)]
+fn open_with_history(board: &mut Board, pos: (usize, usize)) {
+    let before_squares = vec![board.get_square(pos).unwrap().clone()];
+    *board.get_square(pos).unwrap().is_open.borrow_mut() = true;
+    board.add_squares_history(before_squares);
+}
+#[test]
+fn test_board_branches() {
+    let mut board = Board::new(3, 1).unwrap();
+    assert_eq!(board.get_branches(), vec![Branch { moves: 0, is_current: true }]);
+    open_with_history(&mut board, (0, 0));
+    board.add_checkpoint("first");
+    open_with_history(&mut board, (1, 0));
+    assert_eq!(board.to_string().as_str(), "    []");
+
+    // 戻してから別のマスを開けると枝分かれする
+    board.back_squares_history();
+    open_with_history(&mut board, (2, 0));
+    assert_eq!(board.to_string().as_str(), "  []  ");
+    assert_eq!(board.get_branches(),
+               vec![Branch { moves: 2, is_current: false },
+                    Branch { moves: 2, is_current: true }]);
+
+    board.jump_to_branch(0).unwrap();
+    assert_eq!(board.to_string().as_str(), "    []");
+    assert_eq!(board.get_branches(),
+               vec![Branch { moves: 2, is_current: true },
+                    Branch { moves: 2, is_current: false }]);
+    board.jump_to_branch(1).unwrap();
+    assert_eq!(board.to_string().as_str(), "  []  ");
+    assert!(board.jump_to_branch(2).is_err());
+
+    // 枝の途中に戻ったら、最後に進んだ枝をたどる
+    board.jump_to_checkpoint("first").unwrap();
+    assert_eq!(board.to_string().as_str(), "  [][]");
+    assert_eq!(board.get_checkpoints(), vec![("first".to_string(), 1)]);
+    board.forward_squares_history();
+    assert_eq!(board.to_string().as_str(), "  []  ");
+    assert!(board.jump_to_checkpoint("second").is_err());
+
+    board.back_squares_history();
+    board.back_squares_history();
+    board.add_checkpoint("start");
+    open_with_history(&mut board, (1, 0));
+    assert_eq!(board.get_branches().len(), 3);
+    board.jump_to_branch(0).unwrap();
+    assert_eq!(board.to_string().as_str(), "    []");
+    board.jump_to_checkpoint("start").unwrap();
+    assert_eq!(board.to_string().as_str(), "[][][]");
+}
+#[test]
 fn test_board_to_string() {
     let board = Board::new(1, 1).unwrap();
     assert_eq!(board.to_string().as_str(), "[]");
@@ -357,4 +639,57 @@ fn test_board_to_string_with_cursor() {
                "[][][]\n\
                 [][][]\n\
                 [][]\x1b[7m[]\x1b[27m");
-}
\ No newline at end of file
+}
+#[test]
+fn test_board_to_string_in_window() {
+    let board = Board::new(4, 3).unwrap();
+    *board.get_square((3, 2)).unwrap().is_flag.borrow_mut() = true;
+    assert_eq!(board.to_string_in_window((2, 1), (1, 1), (3, 2)).as_str(),
+               "[]\x1b[7m[]\x1b[27m[]\n\
+                [][]\x1b[93m/>\x1b[0m");
+    // 盤面からはみ出す分は切り捨てる
+    assert_eq!(board.to_string_in_window((0, 0), (3, 2), (5, 5)).as_str(),
+               "\x1b[93m/>\x1b[0m");
+    assert_eq!(board.to_string_in_window((0, 0), (0, 0), (4, 3)),
+               board.to_string_with_cursor((0, 0)));
+}
+#[test]
+fn test_board_to_string_with_rulers() {
+    let board = Board::new(3, 10).unwrap();
+    *board.get_square((1, 9)).unwrap().is_flag.borrow_mut() = true;
+    assert_eq!(board.get_ruler_size(), (3, 1));
+    assert_eq!(board.to_string_with_rulers((0, 0), (0, 0), (3, 10)).lines().take(2)
+                   .collect::<Vec<&str>>(),
+               vec!["   A B C", " 1 \x1b[7m[]\x1b[27m[][]"]);
+    // 表示する範囲の座標を付ける
+    assert_eq!(board.to_string_with_rulers((0, 0), (1, 8), (2, 5)),
+               "   B C\n \
+                 9 [][]\n\
+                10 \x1b[93m/>\x1b[0m[]");
+
+    // ２文字以上の名前は縦に並べる
+    let board = Board::new(705, 1).unwrap();
+    assert_eq!(board.get_ruler_size(), (2, 3));
+    assert_eq!(board.to_string_with_rulers((0, 0), (700, 0), (5, 1)),
+               "      A A A\n  \
+                Z Z A A A\n  \
+                Y Z A B C\n\
+                1 [][][][][]");
+    assert_eq!(board.to_string_with_rulers((0, 0), (0, 0), (2, 1)).lines().take(3)
+                   .collect::<Vec<&str>>(),
+               vec!["", "", "  A B"]);
+}
+#[test]
+fn test_board_snapshot() {
+    use crate::screen::vt::Vt;
+
+    // エスケープシーケンスの並びではなく、端末での見え方を比べる
+    let board = Board::new(3, 2).unwrap();
+    *board.get_square((0, 0)).unwrap().is_flag.borrow_mut() = true;
+    *board.get_square((1, 1)).unwrap().is_open.borrow_mut() = true;
+    *board.get_square((2, 1)).unwrap().is_open.borrow_mut() = true;
+    *board.get_square((2, 1)).unwrap().number.borrow_mut() = 3;
+    let mut vt = Vt::new(2, 6);
+    vt.feed(&board.to_string_with_cursor((0, 0)));
+    assert_eq!(vt.to_snapshot(), vec!["{7;93}/>{}[][]", "[]  ３"]);
+}
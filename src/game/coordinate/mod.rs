@@ -0,0 +1,84 @@
+#![allow(dead_code)]
+
+// 表計算ソフトと同じようにマスの座標を書く
+// 列はAからZ、その次はAA, AB..ZZ, AAA..、行は1から数える
+
+// 0始まりのxの列の名前
+pub fn column_name(x: usize) -> String {
+    let mut name = Vec::new();
+    let mut n = x + 1;
+    while 0 < n {
+        n -= 1;
+        name.push((b'A' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    name.iter().rev().collect()
+}
+
+// 列の名前から0始まりのx。小文字でもよい
+pub fn parse_column(name: &str) -> Result<usize, String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!("invalid column '{}'", name));
+    }
+    name.to_ascii_uppercase().bytes()
+        .try_fold(0usize, |n, c| {
+            n.checked_mul(26).and_then(|n| n.checked_add((c - b'A') as usize + 1))
+        })
+        .map(|n| n - 1)
+        .ok_or_else(|| format!("invalid column '{}'", name))
+}
+
+// 0始まりのyの行の名前
+pub fn row_name(y: usize) -> String {
+    (y + 1).to_string()
+}
+
+// 1から数える行の名前から0始まりのy
+pub fn parse_row(name: &str) -> Result<usize, String> {
+    match name.parse::<usize>() {
+        Ok(n) if 0 < n => Ok(n - 1),
+        _ => Err(format!("invalid row '{}'", name))
+    }
+}
+
+// (x, y)の座標の名前。例: (2, 6) -> "C7"
+pub fn position_name(position: (usize, usize)) -> String {
+    format!("{}{}", column_name(position.0), row_name(position.1))
+}
+
+// "C7"のような座標の名前から(x, y)
+pub fn parse_position(name: &str) -> Result<(usize, usize), String> {
+    let split = name.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(name.len());
+    let (column, row) = name.split_at(split);
+    match (parse_column(column), parse_row(row)) {
+        (Ok(x), Ok(y)) => Ok((x, y)),
+        _ => Err(format!("invalid coordinate '{}'", name))
+    }
+}
+
+#[test]
+fn test_column_name() {
+    assert_eq!(column_name(0), "A");
+    assert_eq!(column_name(25), "Z");
+    assert_eq!(column_name(26), "AA");
+    assert_eq!(column_name(701), "ZZ");
+    assert_eq!(column_name(702), "AAA");
+    for x in 0..1000 {
+        assert_eq!(parse_column(&column_name(x)), Ok(x));
+    }
+    assert_eq!(parse_column("ab"), Ok(27));
+    assert!(parse_column("").is_err());
+    assert!(parse_column("A1").is_err());
+}
+#[test]
+fn test_position_name() {
+    assert_eq!(position_name((2, 6)), "C7");
+    assert_eq!(parse_position("C7"), Ok((2, 6)));
+    assert_eq!(parse_position("aa10"), Ok((26, 9)));
+    assert_eq!(parse_row("1"), Ok(0));
+    assert!(parse_row("0").is_err());
+    assert_eq!(parse_position("C0"), Err("invalid coordinate 'C0'".to_string()));
+    assert!(parse_position("7").is_err());
+    assert!(parse_position("C").is_err());
+    assert!(parse_position("C7x").is_err());
+}
@@ -1,11 +1,32 @@
 #![allow(dead_code)]
 
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::time::Duration;
+
 extern crate rand;
-use rand::Rng;
+extern crate rand_chacha;
+use rand::{Rng, SeedableRng};
+use rand::seq::SliceRandom;
+use rand_chacha::ChaChaRng;
 
 mod board;
 use crate::game::board::Board;
 use crate::game::board::Square;
+pub use crate::game::board::Branch;
+
+mod solver;
+
+mod timer;
+use crate::game::timer::Timer;
+pub use crate::game::timer::format_duration;
+
+pub mod coordinate;
+
+use crate::theme::Theme;
+
+// 推論だけで解ける盤面を探すときに試す回数の上限
+const MAX_NO_GUESS_ATTEMPTS: usize = 1000;
 
 pub struct Score {
     number_of_flags: usize,
@@ -13,17 +34,118 @@ pub struct Score {
     number_of_unopened_squares: usize,
     number_of_opened_mines: usize,
     number_of_opened_squares: usize,
+    elapsed: Duration,
 }
 
 impl Score {
-    pub fn to_string(&self) -> String {
-        format!("[] {}  |  [\x1b[91m<>\x1b[0m] {}  |  \x1b[93m/>\x1b[0m {}  |  \
-                \x1b[91m<>\x1b[0m {}  |  [\x1b[91m<>\x1b[0m] - \x1b[93m/>\x1b[0m = {}",
-                self.number_of_unopened_squares,
-                self.number_of_unopened_mines,
-                self.number_of_flags,
-                self.number_of_opened_mines,
-                self.number_of_unopened_mines as isize - self.number_of_flags as isize)
+    // テーマのグリフを使った表示
+    pub fn to_string_with_theme(&self, theme: &Theme) -> String {
+        let unopened = theme.get_unopened().to_short_string();
+        let flag = theme.get_flag().to_short_string();
+        let mine = theme.get_mine().to_short_string();
+        format!("{} {}  |  [{}] {}  |  {} {}  |  {} {}  |  [{}] - {} = {}  |  {}",
+                unopened, self.number_of_unopened_squares,
+                mine, self.number_of_unopened_mines,
+                flag, self.number_of_flags,
+                mine, self.number_of_opened_mines,
+                mine, flag,
+                self.number_of_unopened_mines as isize - self.number_of_flags as isize,
+                format_duration(self.elapsed))
+    }
+}
+
+// ゲームの進行状態
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum GameStatus {
+    NotStarted,
+    Playing,
+    Won,
+    // 爆発したマスの座標 (x, y)
+    Lost((usize, usize)),
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        matches!(self, GameStatus::Won | GameStatus::Lost(_))
+    }
+}
+
+// 地雷の数の指定方法
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Mines {
+    // 地雷の個数
+    Count(usize),
+    // 盤面全体のマスに対する地雷の割合 (%)
+    Density(usize),
+}
+
+impl Mines {
+    // 盤面のマスの数から地雷の個数を求める
+    pub fn count_for(&self, number_of_squares: usize) -> Result<usize, String> {
+        match *self {
+            Mines::Count(count) => Ok(count),
+            Mines::Density(percent) => {
+                if percent > 100 {
+                    return Err("density must be 100% or less".to_string());
+                }
+                Ok(number_of_squares * percent / 100)
+            }
+        }
+    }
+}
+
+// "10"は個数、"15%"は割合として解釈する
+impl FromStr for Mines {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mines, String> {
+        if let Some(percent) = s.strip_suffix('%') {
+            return match usize::from_str(percent) {
+                Ok(percent) => Ok(Mines::Density(percent)),
+                Err(_) => Err("density cannot convert to usize".to_string())
+            };
+        }
+
+        match usize::from_str(s) {
+            Ok(count) => Ok(Mines::Count(count)),
+            Err(_) => Err("mines cannot convert to usize".to_string())
+        }
+    }
+}
+
+// 最初に開けるマスの保護の仕方
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FirstClick {
+    // 最初に開けたマスには地雷を置かない
+    Safe,
+    // 最初に開けたマスとその周囲のマスに地雷を置かない
+    // 最初に開けたマスは必ず0になる
+    Opening,
+    // 保護しない
+    Unprotected,
+}
+
+impl FirstClick {
+    // 地雷を置かないマスの数の最大値
+    fn max_reserved_squares(&self, board_size: (usize, usize)) -> usize {
+        match self {
+            FirstClick::Safe => 1,
+            FirstClick::Opening => board_size.0.min(3) * board_size.1.min(3),
+            FirstClick::Unprotected => 0,
+        }
+    }
+}
+
+impl FromStr for FirstClick {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FirstClick, String> {
+        match s {
+            "safe" => Ok(FirstClick::Safe),
+            "opening" => Ok(FirstClick::Opening),
+            "none" => Ok(FirstClick::Unprotected),
+            _ => Err(format!("unknown first click mode: {}", s))
+        }
     }
 }
 
@@ -33,39 +155,150 @@ pub struct Game {
     // (x, y)
     cursor: (usize, usize),
     board: Board,
+    status: GameStatus,
+    // 最後に開けてしまった地雷の座標 (x, y)
+    detonated: Option<(usize, usize)>,
+    // 置く地雷の数
+    mines: usize,
+    first_click: FirstClick,
+    // 地雷は最初にマスを開けたときに置く
+    is_mines_placed: bool,
+    // trueなら推論だけで解ける盤面にする
+    is_no_guess: bool,
+    // trueなら開いている数字のマスを開けようとしたときにchordする
+    is_open_chord: bool,
+    // 盤面の生成に失敗したときのエラー
+    error: Option<String>,
+    // 大きさと地雷の数、最初に開けるマスが同じなら、同じシード値で同じ地雷の配置になる
+    // 最初に開けるマスは地雷を置かずに保護するので、開けるマスが違えば配置が変わることがある
+    seed: u64,
+    rng: ChaChaRng,
+    // 最初にマスを開けたときに動き始め、勝ち負けが決まるか一時停止すると止まる
+    timer: Timer,
+    is_paused: bool,
 }
 
 impl Game {
     pub fn new(height: usize, width: usize) -> Result<Game, String> {
-        let board = match Board::new(width, height) {
-            Ok(board) => board,
-            Err(e) => return Err(e)
-        };
+        Game::new_with_seed(height, width, rand::thread_rng().gen())
+    }
+
+    pub fn new_with_seed(height: usize, width: usize, seed: u64) -> Result<Game, String> {
+        let board = Board::new(width, height)?;
 
         let game = Game {
             cursor: (0, 0),
             board_size: board.get_size(),
-            board
+            board,
+            status: GameStatus::NotStarted,
+            detonated: None,
+            mines: 0,
+            first_click: FirstClick::Safe,
+            is_mines_placed: false,
+            is_no_guess: false,
+            is_open_chord: false,
+            error: None,
+            seed,
+            rng: ChaChaRng::seed_from_u64(seed),
+            timer: Timer::new(),
+            is_paused: false,
         };
 
         Ok(game)
     }
 
-    pub fn set_mines(&mut self, times: usize) {
-        self.set_mines_to_squares(times);
-        self.remove_mine_and_open();
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    // set_minesより前に呼ぶ
+    pub fn set_first_click(&mut self, first_click: FirstClick) {
+        self.first_click = first_click;
+    }
+
+    pub fn set_no_guess(&mut self, is_no_guess: bool) {
+        self.is_no_guess = is_no_guess;
+    }
+
+    pub fn set_open_chord(&mut self, is_open_chord: bool) {
+        self.is_open_chord = is_open_chord;
+    }
+
+    // 最後に起きたエラーを取り出す
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    // 地雷の数を決める。地雷は最初にマスを開けたときに置かれる
+    pub fn set_mines(&mut self, mines: Mines) -> Result<(), String> {
+        let number_of_squares = self.board_size.0 * self.board_size.1;
+        let count = mines.count_for(number_of_squares)?;
+        let max = number_of_squares - self.first_click.max_reserved_squares(self.board_size);
+        if max < count {
+            return Err(format!("mines must be {} or less", max));
+        }
+
+        self.mines = count;
+        Ok(())
+    }
+
+    // firstのマスを開けても負けないように地雷を置く
+    // is_no_guessがtrueなら、推論だけで解ける盤面が見つかるまで置き直す
+    fn place_mines(&mut self, first: (usize, usize)) -> Result<(), String> {
+        let attempts = if self.is_no_guess { MAX_NO_GUESS_ATTEMPTS } else { 1 };
+
+        for _ in 0..attempts {
+            self.remove_mines();
+            self.set_mines_to_squares(first, self.mines);
+            self.set_numbers_to_squares();
+
+            if !self.is_no_guess || solver::is_solvable(&self.board, first) {
+                self.is_mines_placed = true;
+                return Ok(());
+            }
+        }
+
+        self.remove_mines();
         self.set_numbers_to_squares();
+        Err(format!("could not find a board solvable without guessing in {} attempts. \
+                     try fewer mines", attempts))
     }
 
-    fn set_mines_to_squares(&self, times: usize) {
-        let mut rng = rand::thread_rng();
+    fn remove_mines(&self) {
+        for y in 0..self.board_size.0 {
+            for x in 0..self.board_size.1 {
+                *self.board.get_square((x, y)).unwrap().is_mine.borrow_mut() = false;
+            }
+        }
+    }
 
-        for i in 0..times {
-            let y = rng.gen_range(0, self.board_size.0);
-            let x = rng.gen_range(0, self.board_size.1);
-            let square = self.board.get_square((x, y)).unwrap();
+    // firstのマスを開けたときに保護するマスならtrue
+    fn is_reserved(&self, pos: (usize, usize), first: (usize, usize)) -> bool {
+        match self.first_click {
+            FirstClick::Safe => pos == first,
+            FirstClick::Opening => pos.0 + 1 >= first.0 && pos.0 <= first.0 + 1
+                                   && pos.1 + 1 >= first.1 && pos.1 <= first.1 + 1,
+            FirstClick::Unprotected => false,
+        }
+    }
 
-            *square.is_mine.borrow_mut() = true;
+    // シード値から決まる順にマスを並べて、保護するマスを除いた先頭のcount個を地雷にする
+    // 最初に開けるマスが違っても、保護するマスの周り以外は同じ配置になる
+    fn set_mines_to_squares(&mut self, first: (usize, usize), count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        let mut positions = Vec::with_capacity(self.board_size.0 * self.board_size.1);
+        for y in 0..self.board_size.0 {
+            for x in 0..self.board_size.1 {
+                positions.push((x, y));
+            }
+        }
+        positions.shuffle(&mut self.rng);
+
+        for &pos in positions.iter().filter(|&&pos| !self.is_reserved(pos, first)).take(count) {
+            *self.board.get_square(pos).unwrap().is_mine.borrow_mut() = true;
         }
     }
 
@@ -75,9 +308,9 @@ impl Game {
                 let square = self.board.get_square((x,y)).unwrap();
                 let around_squares = self.board.get_around_squares_of(square);
                 let mut count_of_mines = 0;
-                for (direction, around_square) in around_squares {
+                for (_, around_square) in around_squares {
                     if let Some(s) = around_square {
-                        if *s.is_mine.borrow() == true {
+                        if *s.is_mine.borrow() {
                             count_of_mines += 1;
                         }
                     }
@@ -87,31 +320,41 @@ impl Game {
         }
     }
 
-    // 辺にあるマスの地雷を削除して、マスを開く。
-    fn remove_mine_and_open(&self) {
-        for h in 0..self.board_size.0 {
-            let square = self.board.get_square((0, h)).unwrap();
-            *square.is_mine.borrow_mut() = false;
-            *square.is_open.borrow_mut() = true;
+    pub fn board_to_string(&self) -> String {
+        self.board.to_string_with_cursor(self.cursor)
+    }
 
-            let square = self.board.get_square((self.board_size.1-1, h)).unwrap();
-            *square.is_mine.borrow_mut() = false;
-            *square.is_open.borrow_mut() = true;
-        }
+    // 盤面のうちoriginのマスから(列数, 行数)がsizeの範囲だけを文字列にする
+    pub fn board_window_to_string(&self, origin: (usize, usize), size: (usize, usize)) -> String {
+        self.board.to_string_in_window(self.cursor, origin, size)
+    }
+
+    // 列の名前と行の番号を付けたboard_window_to_string
+    pub fn board_window_to_string_with_rulers(&self, origin: (usize, usize),
+                                              size: (usize, usize)) -> String {
+        self.board.to_string_with_rulers(self.cursor, origin, size)
+    }
 
-        for w in 0..self.board_size.1 {
-            let square = self.board.get_square((w, 0)).unwrap();
-            *square.is_mine.borrow_mut() = false;
-            *square.is_open.borrow_mut() = true;
+    // 列の名前と行の番号が使う(列数, 行数)
+    pub fn get_ruler_size(&self) -> (usize, usize) {
+        self.board.get_ruler_size()
+    }
 
-            let square = self.board.get_square((w, self.board_size.0-1)).unwrap();
-            *square.is_mine.borrow_mut() = false;
-            *square.is_open.borrow_mut() = true;
-        }
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.board.set_theme(theme);
     }
 
-    pub fn board_to_string(&self) -> String {
-        self.board.to_string_with_cursor(self.cursor)
+    pub fn get_theme(&self) -> &Theme {
+        self.board.get_theme()
+    }
+
+    // (height, width)
+    pub fn get_board_size(&self) -> (usize, usize) {
+        self.board_size
+    }
+
+    pub fn get_cursor(&self) -> (usize, usize) {
+        self.cursor
     }
 
     pub fn get_score(&self) -> Score {
@@ -125,7 +368,7 @@ impl Game {
             for x in 0..self.board_size.1 {
                 let square = self.board.get_square((x,y)).unwrap();
 
-                if *square.is_open.borrow() == false {
+                if !*square.is_open.borrow() {
                     if *square.is_flag.borrow() {
                         number_of_flags += 1;
                     }
@@ -134,7 +377,7 @@ impl Game {
                         number_of_unopened_mines += 1;
                     }
 
-                    if *square.is_mine.borrow() == false {
+                    if !*square.is_mine.borrow() {
                         number_of_unopened_squares += 1;
                     }
                 } else {
@@ -142,7 +385,7 @@ impl Game {
                         number_of_opened_mines += 1;
                     }
 
-                    if *square.is_mine.borrow() == false {
+                    if !*square.is_mine.borrow() {
                         number_of_opened_squares += 1;
                     }
                 }
@@ -150,53 +393,193 @@ impl Game {
             }
         }
 
+        // 地雷を置く前は置く予定の数を表示する
+        if !self.is_mines_placed {
+            number_of_unopened_mines = self.mines;
+            number_of_unopened_squares -= self.mines;
+        }
+
         Score {
             number_of_flags,
             number_of_unopened_mines,
             number_of_unopened_squares,
             number_of_opened_mines,
             number_of_opened_squares,
+            elapsed: self.timer.get_elapsed(),
         }
     }
 
+    pub fn get_status(&self) -> GameStatus {
+        self.status
+    }
+
+    // 盤面からゲームの状態を求める
+    // 開いた地雷があれば負け、地雷以外のマスがすべて開いていれば勝ち
+    // 負けたときは、開けてしまった地雷がまだ開いていればその座標を返す
+    fn check_status(&self) -> GameStatus {
+        if let Some(position) = self.detonated {
+            let square = self.board.get_square(position).unwrap();
+            if *square.is_open.borrow() && *square.is_mine.borrow() {
+                return GameStatus::Lost(position);
+            }
+        }
+
+        let mut is_any_opened = false;
+        let mut is_all_safe_opened = true;
+
+        for y in 0..self.board_size.0 {
+            for x in 0..self.board_size.1 {
+                let square = self.board.get_square((x,y)).unwrap();
+                let is_open = *square.is_open.borrow();
+                let is_mine = *square.is_mine.borrow();
+
+                if is_open && is_mine {
+                    return GameStatus::Lost((x, y));
+                }
+                if is_open {
+                    is_any_opened = true;
+                }
+                if !is_open && !is_mine {
+                    is_all_safe_opened = false;
+                }
+            }
+        }
+
+        if !is_any_opened {
+            GameStatus::NotStarted
+        } else if is_all_safe_opened {
+            GameStatus::Won
+        } else {
+            GameStatus::Playing
+        }
+    }
+
+    fn update_status(&mut self) {
+        self.status = self.check_status();
+        self.update_timer();
+    }
+
+    // 遊んでいる間だけ時計を動かす
+    fn update_timer(&mut self) {
+        if self.status == GameStatus::Playing && !self.is_paused {
+            self.timer.start();
+        } else {
+            self.timer.stop();
+        }
+    }
+
+    pub fn get_elapsed(&self) -> Duration {
+        self.timer.get_elapsed()
+    }
+
+    pub fn is_timer_running(&self) -> bool {
+        self.timer.is_running()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    // 一時停止中はマスを操作できない
+    pub fn toggle_pause(&mut self) {
+        self.is_paused = !self.is_paused;
+        self.update_timer();
+    }
+
+    // ゲームが終わっているか一時停止中ならtrue
+    pub fn is_locked(&self) -> bool {
+        self.status.is_over() || self.is_paused
+    }
+
     pub fn back_history(&mut self) {
         self.board.back_squares_history();
+        self.update_status();
+    }
+
+    pub fn forward_history(&mut self) {
+        self.board.forward_squares_history();
+        self.update_status();
+    }
+
+    pub fn add_checkpoint(&mut self, name: &str) {
+        self.board.add_checkpoint(name);
+    }
+
+    // (名前, 手数)
+    pub fn get_checkpoints(&self) -> Vec<(String, usize)> {
+        self.board.get_checkpoints()
+    }
+
+    pub fn jump_to_checkpoint(&mut self, name: &str) -> Result<(), String> {
+        self.board.jump_to_checkpoint(name)?;
+        self.update_status();
+        Ok(())
+    }
+
+    pub fn get_branches(&self) -> Vec<Branch> {
+        self.board.get_branches()
+    }
+
+    pub fn jump_to_branch(&mut self, index: usize) -> Result<(), String> {
+        self.board.jump_to_branch(index)?;
+        self.update_status();
+        Ok(())
     }
 
     // is_open, is_mine, is_flagがtrueのマスは伝播しない
-    // numberが0のマスは自身のマスを開けた後、四方のマスに伝播する
+    // numberが0のマスは自身のマスを開けた後、斜めも含めた周囲の８マスに伝播する
     // numberが0でないマスは自身を開けるが伝播しない
     // 自身のマスを変更したら、before_squaresに追加する
-    fn open_adjacent_squares(&self,
-                             center_square: &Square,
-                             before_squares: &mut Vec<Square>) {
-        let around_squares = self.board.get_around_squares_of(center_square);
-        for (direction, square) in &around_squares[0..4] {
-            let square = match square {
-                Some(square) => *square,
-                None => continue
-            };
+    // 再帰すると広い盤面でスタックが溢れるので、キューを使って順に開ける
+    fn open_adjacent_squares<'a>(&'a self,
+                                 center_square: &'a Square,
+                                 before_squares: &mut Vec<Square>) {
+        let mut queue = VecDeque::new();
+        queue.push_back(center_square);
+
+        while let Some(center_square) = queue.pop_front() {
+            for (_, square) in self.board.get_around_squares_of(center_square) {
+                let square = match square {
+                    Some(square) => square,
+                    None => continue
+                };
+
+                if *square.is_mine.borrow() || *square.is_open.borrow()
+                    || *square.is_flag.borrow() {
+                    continue
+                }
 
-            if *square.is_mine.borrow() || *square.is_open.borrow()
-                || *square.is_flag.borrow(){
-                continue
-            }
-            if *square.number.borrow() != 0 {
                 before_squares.push(square.clone());
                 *square.is_open.borrow_mut() = true;
-                continue
+                if *square.number.borrow() == 0 {
+                    queue.push_back(square);
+                }
             }
-
-            before_squares.push(square.clone());
-            *square.is_open.borrow_mut() = true;
-            self.open_adjacent_squares(square, before_squares);
         }
     }
 
     pub fn open(&mut self) {
+        if self.is_locked() {
+            return;
+        }
+
+        if !self.is_mines_placed {
+            let square = self.board.get_square(self.cursor).unwrap();
+            if *square.is_flag.borrow() {
+                return;
+            }
+            if let Err(e) = self.place_mines(self.cursor) {
+                self.error = Some(e);
+                return;
+            }
+        }
+
         let square = self.board.get_square(self.cursor).unwrap();
 
-        if *square.is_open.borrow() || *square.is_flag.borrow() == true {
+        if *square.is_open.borrow() && self.is_open_chord {
+            return self.chord();
+        }
+        if *square.is_open.borrow() || *square.is_flag.borrow() {
             return;
         }
 
@@ -205,12 +588,69 @@ impl Game {
 
         *square.is_open.borrow_mut() = true;
 
-
-        if *square.number.borrow() == 0 {
+        if *square.is_mine.borrow() {
+            self.detonated = Some(self.cursor);
+        }
+        if *square.number.borrow() == 0 && !*square.is_mine.borrow() {
             self.open_adjacent_squares(square, &mut before_squares);
         }
 
         self.board.add_squares_history(before_squares);
+        self.update_status();
+    }
+
+    // 開いている数字のマスの周りの旗の数が数字と同じなら、
+    // 周りの旗のないマスをすべて開ける。旗が間違っていれば地雷を開けることになる
+    pub fn chord(&mut self) {
+        if self.is_locked() {
+            return;
+        }
+
+        let square = self.board.get_square(self.cursor).unwrap();
+        let number = *square.number.borrow();
+        if !*square.is_open.borrow() || *square.is_mine.borrow() || number == 0 {
+            return;
+        }
+
+        let around_squares = self.board.get_around_squares_of(square);
+        let number_of_flags = around_squares.iter()
+            .filter(|(_, s)| matches!(s, Some(s) if *s.is_flag.borrow()))
+            .count();
+        if number_of_flags != number {
+            return;
+        }
+
+        let mut before_squares = Vec::new();
+        let mut detonated = None;
+        for (_, around_square) in &around_squares {
+            let around_square = match around_square {
+                Some(around_square) => *around_square,
+                None => continue
+            };
+            if *around_square.is_open.borrow() || *around_square.is_flag.borrow() {
+                continue;
+            }
+
+            before_squares.push(around_square.clone());
+            *around_square.is_open.borrow_mut() = true;
+
+            // 旗が間違っていて地雷を開けたら、最初に開けた地雷で爆発する
+            if *around_square.is_mine.borrow() && detonated.is_none() {
+                detonated = Some(around_square.get_pos());
+            }
+            if *around_square.number.borrow() == 0 && !*around_square.is_mine.borrow() {
+                self.open_adjacent_squares(around_square, &mut before_squares);
+            }
+        }
+
+        if before_squares.is_empty() {
+            return;
+        }
+        if detonated.is_some() {
+            self.detonated = detonated;
+        }
+        self.board.add_squares_history(before_squares);
+        self.update_status();
     }
 
     pub fn open_all_squares(&mut self) {
@@ -224,9 +664,14 @@ impl Game {
         }
 
         self.board.add_squares_history(before_squares);
+        self.update_status();
     }
 
     pub fn toggle_flag(&mut self) {
+        if self.is_locked() {
+            return;
+        }
+
         let square = self.board.get_square(self.cursor).unwrap();
 
         if *square.is_open.borrow() {
@@ -239,13 +684,12 @@ impl Game {
         *square.is_flag.borrow_mut() = !is_flag;
 
         self.board.add_squares_history(vec![before_square]);
+        self.update_status();
     }
 
     pub fn cursor(&mut self, cursor: (usize, usize)) {
-        if 0 <= cursor.0 || cursor.0 < self.board_size.1 {
-            if 0 <= cursor.1 || cursor.1 < self.board_size.0 {
-                self.cursor = cursor;
-            }
+        if cursor.0 < self.board_size.1 && cursor.1 < self.board_size.0 {
+            self.cursor = cursor;
         }
     }
 
@@ -344,6 +788,7 @@ fn test_game_open() {
     game.open();
     assert_eq!(game.board_to_string().as_str(),
                "\x1b[7m  \x1b[27m");
+    assert_eq!(game.get_status(), GameStatus::Won);
 }
 #[test]
 fn test_game_open_all_squares() {
@@ -382,3 +827,356 @@ fn test_game_set_mines() {
                 １\x1b[91m<>\x1b[0m１\n\
                 １１１");
 }
+#[test]
+fn test_mines_from_str() {
+    assert_eq!(Mines::from_str("10"), Ok(Mines::Count(10)));
+    assert_eq!(Mines::from_str("15%"), Ok(Mines::Density(15)));
+    assert!(Mines::from_str("a").is_err());
+    assert!(Mines::from_str("a%").is_err());
+    assert_eq!(Mines::Density(15).count_for(100), Ok(15));
+    assert_eq!(Mines::Density(50).count_for(9), Ok(4));
+    assert!(Mines::Density(101).count_for(100).is_err());
+}
+#[test]
+fn test_game_set_mines_count() {
+    for count in 0..=99 {
+        let mut game = Game::new(10, 10).unwrap();
+        game.set_mines(Mines::Count(count)).unwrap();
+        assert_eq!(game.get_score().number_of_unopened_mines, count);
+        game.cursor((count % 10, count / 10));
+        game.open();
+        let score = game.get_score();
+        assert_eq!(score.number_of_unopened_mines, count);
+        assert_eq!(score.number_of_opened_mines, 0);
+    }
+    let mut game = Game::new(10, 10).unwrap();
+    assert_eq!(game.set_mines(Mines::Count(100)),
+               Err("mines must be 99 or less".to_string()));
+    let mut game = Game::new(10, 10).unwrap();
+    game.set_mines(Mines::Density(20)).unwrap();
+    game.open();
+    assert_eq!(game.get_score().number_of_unopened_mines, 20);
+}
+#[cfg(test)]
+fn mine_positions(game: &Game) -> Vec<(usize, usize)> {
+    let mut positions = Vec::new();
+    for y in 0..game.board_size.0 {
+        for x in 0..game.board_size.1 {
+            if *game.board.get_square((x, y)).unwrap().is_mine.borrow() {
+                positions.push((x, y));
+            }
+        }
+    }
+    positions
+}
+#[test]
+fn test_game_seed() {
+    let new_game_at = |seed, first_click, first| {
+        let mut game = Game::new_with_seed(5, 5, seed).unwrap();
+        game.set_first_click(first_click);
+        game.set_mines(Mines::Count(5)).unwrap();
+        game.cursor(first);
+        game.open();
+        game
+    };
+    let new_game = |seed, first_click| new_game_at(seed, first_click, (4, 4));
+
+    let game = new_game(1, FirstClick::Unprotected);
+    assert_eq!(game.get_seed(), 1);
+    // 実行するたびに配置が変わらないように、配置そのものを確かめる
+    assert_eq!(mine_positions(&game), vec![(1, 0), (1, 1), (3, 1), (4, 2), (1, 3)]);
+    assert_eq!(mine_positions(&new_game(1, FirstClick::Unprotected)), mine_positions(&game));
+    assert_ne!(mine_positions(&new_game(2, FirstClick::Unprotected)), mine_positions(&game));
+    // 保護するマスに地雷がなければ、保護の仕方が違っても同じ配置になる
+    assert_eq!(mine_positions(&new_game(1, FirstClick::Opening)), mine_positions(&game));
+    // 保護するマスに置くはずだった地雷は、シード値で決まる次のマスに移る
+    // シード値で再現できるのは、最初に開けるマスも同じ場合だけ
+    let game = new_game_at(1, FirstClick::Safe, (1, 0));
+    assert_eq!(mine_positions(&game), vec![(1, 1), (3, 1), (0, 2), (4, 2), (1, 3)]);
+    assert_eq!(mine_positions(&new_game_at(1, FirstClick::Safe, (1, 0))), mine_positions(&game));
+
+    let mut game = Game::new_with_seed(10, 10, 7).unwrap();
+    game.set_first_click(FirstClick::Opening);
+    game.set_no_guess(true);
+    game.set_mines(Mines::Count(15)).unwrap();
+    game.open();
+    let mut same_game = Game::new_with_seed(10, 10, 7).unwrap();
+    same_game.set_first_click(FirstClick::Opening);
+    same_game.set_no_guess(true);
+    same_game.set_mines(Mines::Count(15)).unwrap();
+    same_game.open();
+    assert_eq!(mine_positions(&game), mine_positions(&same_game));
+}
+#[test]
+fn test_game_first_click() {
+    // 周囲のマスも含めて地雷が置かれない
+    for &(x, y) in &[(0, 0), (4, 4), (9, 9), (0, 5)] {
+        let mut game = Game::new(10, 10).unwrap();
+        game.set_first_click(FirstClick::Opening);
+        game.set_mines(Mines::Count(91)).unwrap();
+        game.cursor((x, y));
+        game.open();
+        assert!(matches!(game.get_status(), GameStatus::Playing | GameStatus::Won));
+        let square = game.board.get_square((x, y)).unwrap();
+        assert_eq!(*square.number.borrow(), 0);
+        assert_eq!(game.get_score().number_of_unopened_mines, 91);
+    }
+    let mut game = Game::new(10, 10).unwrap();
+    game.set_first_click(FirstClick::Opening);
+    assert!(game.set_mines(Mines::Count(92)).is_err());
+    let mut game = Game::new(2, 2).unwrap();
+    game.set_first_click(FirstClick::Opening);
+    assert!(game.set_mines(Mines::Count(1)).is_err());
+
+    // 保護しない場合はすべてのマスを地雷にできる
+    let mut game = Game::new(10, 10).unwrap();
+    game.set_first_click(FirstClick::Unprotected);
+    game.set_mines(Mines::Count(100)).unwrap();
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Lost((0, 0)));
+
+    // 最初に開けた状態まで戻しても地雷は置き直さない
+    let mut game = Game::new(10, 10).unwrap();
+    game.set_mines(Mines::Count(50)).unwrap();
+    game.open();
+    game.back_history();
+    assert_eq!(game.get_status(), GameStatus::NotStarted);
+    assert_eq!(game.get_score().number_of_unopened_mines, 50);
+    assert!(!*game.board.get_square((0, 0)).unwrap().is_mine.borrow());
+}
+#[test]
+fn test_game_no_guess() {
+    for _ in 0..10 {
+        let mut game = Game::new(10, 10).unwrap();
+        game.set_first_click(FirstClick::Opening);
+        game.set_no_guess(true);
+        game.set_mines(Mines::Count(15)).unwrap();
+        game.cursor((3, 3));
+        game.open();
+        assert_eq!(game.take_error(), None);
+        assert!(solver::is_solvable(&game.board, (3, 3)));
+        assert_eq!(game.get_score().number_of_unopened_mines, 15);
+    }
+
+    // どのように置いても左右のどちらが地雷か分からない
+    let mut game = Game::new(1, 3).unwrap();
+    game.set_no_guess(true);
+    game.set_mines(Mines::Count(1)).unwrap();
+    game.cursor((1, 0));
+    game.open();
+    assert!(game.take_error().is_some());
+    assert_eq!(game.get_status(), GameStatus::NotStarted);
+    assert_eq!(game.get_score().number_of_opened_squares, 0);
+    assert_eq!(game.take_error(), None);
+}
+#[test]
+fn test_game_open_diagonal() {
+    // 地雷は(2, 0)と(0, 2)。(1, 1)は斜めにしか0のマスと接していない
+    let mut game = Game::new(3, 3).unwrap();
+    *game.board.get_square((2, 0)).unwrap().is_mine.borrow_mut() = true;
+    *game.board.get_square((0, 2)).unwrap().is_mine.borrow_mut() = true;
+    game.set_numbers_to_squares();
+    game.is_mines_placed = true;
+    game.open();
+    assert_eq!(game.board_to_string().as_str(),
+               "\x1b[7m  \x1b[27m１[]\n\
+                １２[]\n\
+                [][][]");
+}
+#[test]
+fn test_game_open_large_board() {
+    // 再帰しないので、広い盤面でもスタックが溢れない
+    let mut game = Game::new(300, 300).unwrap();
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Won);
+    game.back_history();
+    assert_eq!(game.get_status(), GameStatus::NotStarted);
+}
+#[test]
+fn test_game_open_huge_board() {
+    // 地雷がなければ１回で100万マスを開ける
+    let mut game = Game::new(1000, 1000).unwrap();
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Won);
+}
+// 3GB近くのメモリを使い時間もかかるので、cargo test --release -- --ignoredで実行する
+#[test]
+#[ignore]
+fn test_game_open_4000x4000_board() {
+    let mut game = Game::new(4000, 4000).unwrap();
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Won);
+}
+#[test]
+fn test_game_forward_history() {
+    let mut game = Game::new(3, 3).unwrap();
+    *game.board.get_square((1, 1)).unwrap().is_mine.borrow_mut() = true;
+    game.set_numbers_to_squares();
+    game.is_mines_placed = true;
+    game.open();
+    game.cursor((1, 1));
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Lost((1, 1)));
+    game.back_history();
+    game.back_history();
+    assert_eq!(game.get_status(), GameStatus::NotStarted);
+    game.forward_history();
+    assert_eq!(game.get_status(), GameStatus::Playing);
+    game.forward_history();
+    assert_eq!(game.get_status(), GameStatus::Lost((1, 1)));
+    game.back_history();
+    game.toggle_flag();
+    game.forward_history();
+    assert_eq!(game.get_status(), GameStatus::Playing);
+    assert_eq!(game.get_score().number_of_flags, 1);
+}
+#[test]
+fn test_game_checkpoint() {
+    let mut game = Game::new(3, 3).unwrap();
+    *game.board.get_square((1, 1)).unwrap().is_mine.borrow_mut() = true;
+    game.set_numbers_to_squares();
+    game.is_mines_placed = true;
+    game.open();
+    game.add_checkpoint("guess");
+    game.cursor((1, 1));
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Lost((1, 1)));
+
+    game.jump_to_checkpoint("guess").unwrap();
+    assert_eq!(game.get_status(), GameStatus::Playing);
+    game.cursor((2, 2));
+    game.open();
+    assert_eq!(game.get_branches().len(), 2);
+    game.jump_to_branch(0).unwrap();
+    assert_eq!(game.get_status(), GameStatus::Lost((1, 1)));
+    game.jump_to_branch(1).unwrap();
+    assert_eq!(game.get_status(), GameStatus::Playing);
+    assert!(game.jump_to_checkpoint("none").is_err());
+}
+#[test]
+fn test_game_timer() {
+    let mut game = Game::new(3, 3).unwrap();
+    *game.board.get_square((1, 1)).unwrap().is_mine.borrow_mut() = true;
+    game.set_numbers_to_squares();
+    game.is_mines_placed = true;
+    assert!(!game.is_timer_running());
+    game.toggle_flag();
+    assert!(!game.is_timer_running());
+    game.toggle_flag();
+    game.open();
+    assert!(game.is_timer_running());
+
+    // 一時停止中は時計が止まり、マスを操作できない
+    game.toggle_pause();
+    assert!(game.is_paused());
+    assert!(!game.is_timer_running());
+    let elapsed = game.get_elapsed();
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(game.get_elapsed(), elapsed);
+    game.cursor((1, 1));
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Playing);
+    game.toggle_pause();
+    assert!(game.is_timer_running());
+
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Lost((1, 1)));
+    assert!(!game.is_timer_running());
+    let elapsed = game.get_elapsed();
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(game.get_elapsed(), elapsed);
+    game.back_history();
+    assert!(game.is_timer_running());
+}
+#[test]
+fn test_game_chord() {
+    // 地雷は(0, 0)と(2, 2)
+    let mut game = Game::new(3, 3).unwrap();
+    *game.board.get_square((0, 0)).unwrap().is_mine.borrow_mut() = true;
+    *game.board.get_square((2, 2)).unwrap().is_mine.borrow_mut() = true;
+    game.set_numbers_to_squares();
+    game.is_mines_placed = true;
+
+    game.cursor((1, 1));
+    game.open();
+    // 旗が足りなければ何もしない
+    game.chord();
+    assert_eq!(game.get_score().number_of_opened_squares, 1);
+    game.cursor((0, 0));
+    game.toggle_flag();
+    game.cursor((1, 1));
+    game.chord();
+    assert_eq!(game.get_score().number_of_opened_squares, 1);
+    game.cursor((2, 2));
+    game.toggle_flag();
+    game.cursor((1, 1));
+    game.chord();
+    assert_eq!(game.get_score().number_of_opened_squares, 7);
+    assert_eq!(game.get_status(), GameStatus::Won);
+    // １回で戻せる
+    game.back_history();
+    assert_eq!(game.get_score().number_of_opened_squares, 1);
+    assert_eq!(game.get_status(), GameStatus::Playing);
+
+    // 旗が間違っていれば爆発する
+    game.cursor((2, 2));
+    game.toggle_flag();
+    game.cursor((1, 2));
+    game.toggle_flag();
+    game.cursor((1, 1));
+    game.chord();
+    assert_eq!(game.get_status(), GameStatus::Lost((2, 2)));
+    // 全部開けても、爆発したのは左上の地雷ではない
+    game.open_all_squares();
+    assert_eq!(game.get_status(), GameStatus::Lost((2, 2)));
+
+    // openでもchordできる
+    game.back_history();
+    game.back_history();
+    game.back_history();
+    game.back_history();
+    game.open();
+    assert_eq!(game.get_score().number_of_opened_squares, 1);
+    game.set_open_chord(true);
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Won);
+}
+#[test]
+fn test_first_click_from_str() {
+    assert_eq!(FirstClick::from_str("safe"), Ok(FirstClick::Safe));
+    assert_eq!(FirstClick::from_str("opening"), Ok(FirstClick::Opening));
+    assert_eq!(FirstClick::from_str("none"), Ok(FirstClick::Unprotected));
+    assert!(FirstClick::from_str("border").is_err());
+}
+#[test]
+fn test_game_status() {
+    let mut game = Game::new(3, 3).unwrap();
+    assert_eq!(game.get_status(), GameStatus::NotStarted);
+    *game.board.get_square((1, 1)).unwrap().is_mine.borrow_mut() = true;
+    game.set_numbers_to_squares();
+    game.is_mines_placed = true;
+    game.toggle_flag();
+    assert_eq!(game.get_status(), GameStatus::NotStarted);
+    game.toggle_flag();
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Playing);
+    game.cursor((1, 1));
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Lost((1, 1)));
+    // 終了後は操作を受け付けない
+    game.cursor((2, 2));
+    game.open();
+    game.toggle_flag();
+    assert!(!*game.board.get_square((2, 2)).unwrap().is_open.borrow());
+    assert!(!*game.board.get_square((2, 2)).unwrap().is_flag.borrow());
+    game.back_history();
+    assert_eq!(game.get_status(), GameStatus::Playing);
+    for &pos in &[(1, 0), (2, 0), (0, 1), (2, 1), (0, 2), (1, 2)] {
+        game.cursor(pos);
+        game.open();
+        assert_eq!(game.get_status(), GameStatus::Playing);
+    }
+    game.cursor((2, 2));
+    game.open();
+    assert_eq!(game.get_status(), GameStatus::Won);
+}
@@ -0,0 +1,375 @@
+#![allow(dead_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+
+use crate::game::board::Board;
+
+// ソルバーから見たマスの状態
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Cell {
+    Unknown,
+    Opened,
+    Mine,
+}
+
+// 数字のマスから分かる条件
+// unknownsのマスの中にmines個の地雷がある
+#[derive(Debug, PartialEq, Clone)]
+struct Constraint {
+    unknowns: Vec<(usize, usize)>,
+    mines: usize,
+}
+
+// 推論だけで盤面を解けるかを調べるソルバー
+// 盤面の地雷の位置は、推論で安全と分かったマスの数字を見るためだけに使う
+pub struct Solver<'a> {
+    board: &'a Board,
+    // (height, width)
+    size: (usize, usize),
+    cells: Vec<Vec<Cell>>,
+    number_of_mines: usize,
+    // 開いていないマスが周りに残っている数字のマスの条件
+    // マスが変わるたびに、その周りの条件だけを作り直す
+    constraints: BTreeMap<(usize, usize), Constraint>,
+    // 条件が変わってから、１つの数字だけで調べていないマス
+    changed_singles: BTreeSet<(usize, usize)>,
+    // 条件が変わってから、２つの数字の包含関係で調べていないマス
+    changed_pairs: BTreeSet<(usize, usize)>,
+    number_of_unknowns: usize,
+    number_of_found_mines: usize,
+}
+
+impl<'a> Solver<'a> {
+    // 盤面で既に開いているマスは開いているものとして始める
+    pub fn new(board: &'a Board) -> Solver<'a> {
+        let size = board.get_size();
+        let mut cells = vec![vec![Cell::Unknown; size.1]; size.0];
+        let mut number_of_mines = 0;
+        let mut opened = Vec::new();
+
+        for (y, line) in cells.iter_mut().enumerate() {
+            for (x, cell) in line.iter_mut().enumerate() {
+                let square = board.get_square((x, y)).unwrap();
+                if *square.is_mine.borrow() {
+                    number_of_mines += 1;
+                } else if *square.is_open.borrow() {
+                    *cell = Cell::Opened;
+                    opened.push((x, y));
+                }
+            }
+        }
+
+        let mut solver = Solver {
+            board,
+            size,
+            cells,
+            number_of_mines,
+            constraints: BTreeMap::new(),
+            changed_singles: BTreeSet::new(),
+            changed_pairs: BTreeSet::new(),
+            number_of_unknowns: size.0 * size.1 - opened.len(),
+            number_of_found_mines: 0,
+        };
+        for pos in opened {
+            solver.update_constraint(pos);
+        }
+        solver
+    }
+
+    // firstのマスを開けてから、推論できなくなるまで解き進める
+    // すべての安全なマスを開けられたらtrue
+    pub fn solve(&mut self, first: (usize, usize)) -> bool {
+        if *self.board.get_square(first).unwrap().is_mine.borrow() {
+            return false;
+        }
+        self.open(first);
+
+        while self.step() {}
+
+        self.is_solved()
+    }
+
+    pub fn is_solved(&self) -> bool {
+        for (y, line) in self.cells.iter().enumerate() {
+            for (x, cell) in line.iter().enumerate() {
+                let is_mine = *self.board.get_square((x, y)).unwrap().is_mine.borrow();
+                if *cell != Cell::Opened && !is_mine {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn around(&self, pos: (usize, usize)) -> Vec<(usize, usize)> {
+        let mut positions = Vec::new();
+        for y in pos.1.saturating_sub(1)..(pos.1 + 2).min(self.size.0) {
+            for x in pos.0.saturating_sub(1)..(pos.0 + 2).min(self.size.1) {
+                if (x, y) != pos {
+                    positions.push((x, y));
+                }
+            }
+        }
+        positions
+    }
+
+    fn number(&self, pos: (usize, usize)) -> usize {
+        *self.board.get_square(pos).unwrap().number.borrow()
+    }
+
+    // 0のマスは周囲のマスも開ける
+    fn open(&mut self, pos: (usize, usize)) {
+        let mut stack = vec![pos];
+        while let Some(pos) = stack.pop() {
+            if self.cells[pos.1][pos.0] != Cell::Unknown {
+                continue;
+            }
+            self.set_cell(pos, Cell::Opened);
+            if self.number(pos) == 0 {
+                stack.extend(self.around(pos));
+            }
+        }
+    }
+
+    fn constraint_of(&self, pos: (usize, usize)) -> Option<Constraint> {
+        if self.cells[pos.1][pos.0] != Cell::Opened {
+            return None;
+        }
+
+        let mut unknowns = Vec::new();
+        let mut known_mines = 0;
+        for around in self.around(pos) {
+            match self.cells[around.1][around.0] {
+                Cell::Unknown => unknowns.push(around),
+                Cell::Mine => known_mines += 1,
+                Cell::Opened => ()
+            }
+        }
+        if unknowns.is_empty() {
+            return None;
+        }
+
+        Some(Constraint {
+            unknowns,
+            mines: self.number(pos) - known_mines,
+        })
+    }
+
+    // posの条件を作り直し、変わっていれば次のstepで調べ直す
+    fn update_constraint(&mut self, pos: (usize, usize)) {
+        match self.constraint_of(pos) {
+            Some(constraint) => {
+                if self.constraints.get(&pos) != Some(&constraint) {
+                    self.constraints.insert(pos, constraint);
+                    self.changed_singles.insert(pos);
+                    self.changed_pairs.insert(pos);
+                }
+            },
+            None => {
+                self.constraints.remove(&pos);
+            }
+        }
+    }
+
+    // 分からなかったマスを開けるか地雷にして、posと周りの条件を作り直す
+    fn set_cell(&mut self, pos: (usize, usize), cell: Cell) {
+        self.cells[pos.1][pos.0] = cell;
+        self.number_of_unknowns -= 1;
+        if cell == Cell::Mine {
+            self.number_of_found_mines += 1;
+        }
+
+        self.update_constraint(pos);
+        for around in self.around(pos) {
+            self.update_constraint(around);
+        }
+    }
+
+    // 地雷か安全かが確定したマスを反映する。変化があればtrue
+    fn apply(&mut self, positions: &[(usize, usize)], is_mine: bool) -> bool {
+        let mut is_changed = false;
+        for &pos in positions {
+            if self.cells[pos.1][pos.0] != Cell::Unknown {
+                continue;
+            }
+            if is_mine {
+                self.set_cell(pos, Cell::Mine);
+            } else {
+                self.open(pos);
+            }
+            is_changed = true;
+        }
+        is_changed
+    }
+
+    // 推論を１段階進める。何も分からなければfalse
+    // 前に調べてから変わった条件だけを調べる
+    fn step(&mut self) -> bool {
+        // １つの数字だけで決まるマス
+        let mut is_changed = false;
+        for pos in mem::take(&mut self.changed_singles) {
+            let constraint = match self.constraints.get(&pos) {
+                Some(constraint) => constraint.clone(),
+                None => continue
+            };
+            if constraint.mines == 0 {
+                is_changed |= self.apply(&constraint.unknowns, false);
+            } else if constraint.mines == constraint.unknowns.len() {
+                is_changed |= self.apply(&constraint.unknowns, true);
+            }
+        }
+        if is_changed {
+            return true;
+        }
+
+        // ２つの数字の包含関係から決まるマス
+        for pos in mem::take(&mut self.changed_pairs) {
+            let mut found = Vec::new();
+            let a = match self.constraints.get(&pos) {
+                Some(a) => a,
+                None => continue
+            };
+            for y in pos.1.saturating_sub(2)..(pos.1 + 3).min(self.size.0) {
+                for x in pos.0.saturating_sub(2)..(pos.0 + 3).min(self.size.1) {
+                    let b = match self.constraints.get(&(x, y)) {
+                        Some(b) if (x, y) != pos => b,
+                        _ => continue
+                    };
+                    found.extend(subtract(a, b));
+                    found.extend(subtract(b, a));
+                }
+            }
+            for (rest, is_mine) in found {
+                is_changed |= self.apply(&rest, is_mine);
+            }
+        }
+        if is_changed {
+            return true;
+        }
+
+        // 残りの地雷の数から決まるマス
+        let rest_mines = self.number_of_mines - self.number_of_found_mines;
+        if self.number_of_unknowns == 0 {
+            return false;
+        }
+        if rest_mines == 0 || rest_mines == self.number_of_unknowns {
+            let mut unknowns = Vec::new();
+            for (y, line) in self.cells.iter().enumerate() {
+                for (x, cell) in line.iter().enumerate() {
+                    if *cell == Cell::Unknown {
+                        unknowns.push((x, y));
+                    }
+                }
+            }
+            return self.apply(&unknowns, rest_mines != 0);
+        }
+
+        false
+    }
+}
+
+// aのマスがすべてbに含まれていれば、bだけにあるマスの中の地雷の数が分かる
+// それで地雷か安全かが決まれば(マス, 地雷ならtrue)
+fn subtract(a: &Constraint, b: &Constraint) -> Option<(Vec<(usize, usize)>, bool)> {
+    if !a.unknowns.iter().all(|p| b.unknowns.contains(p)) {
+        return None;
+    }
+
+    let rest: Vec<(usize, usize)> = b.unknowns.iter()
+        .filter(|p| !a.unknowns.contains(p))
+        .cloned()
+        .collect();
+    let mines = b.mines - a.mines;
+    if rest.is_empty() {
+        None
+    } else if mines == 0 {
+        Some((rest, false))
+    } else if mines == rest.len() {
+        Some((rest, true))
+    } else {
+        None
+    }
+}
+
+// firstのマスから推論だけで解ける盤面ならtrue
+pub fn is_solvable(board: &Board, first: (usize, usize)) -> bool {
+    Solver::new(board).solve(first)
+}
+
+#[cfg(test)]
+fn board_with_mines(height: usize, width: usize, mines: &[(usize, usize)]) -> crate::game::Game {
+    let game = crate::game::Game::new(height, width).unwrap();
+    for &pos in mines {
+        *game.board.get_square(pos).unwrap().is_mine.borrow_mut() = true;
+    }
+    game.set_numbers_to_squares();
+    game
+}
+
+#[test]
+fn test_solver_single_number() {
+    // 1のマスの周りに開いていないマスが１つだけ
+    let game = board_with_mines(1, 4, &[(3, 0)]);
+    assert!(is_solvable(&game.board, (0, 0)));
+    // 地雷を開けたら解けない
+    assert!(!is_solvable(&game.board, (3, 0)));
+}
+#[test]
+fn test_solver_fifty_fifty() {
+    // (2, 0)と(2, 1)のどちらが地雷か分からない
+    let game = board_with_mines(2, 3, &[(2, 0)]);
+    assert!(!is_solvable(&game.board, (0, 0)));
+}
+#[test]
+fn test_solver_subset() {
+    // 下の行はすべて1
+    // (0, 1)の条件が(1, 1)の条件に含まれるので(2, 0)は安全と分かる
+    let game = board_with_mines(2, 3, &[(1, 0)]);
+    let mut solver = Solver::new(&game.board);
+    solver.open((0, 1));
+    solver.open((1, 1));
+    solver.open((2, 1));
+    assert!(solver.step());
+    assert_eq!(solver.cells[0][2], Cell::Opened);
+    while solver.step() {}
+    assert_eq!(solver.cells[0][1], Cell::Mine);
+    assert!(solver.is_solved());
+}
+#[test]
+fn test_solver_mine_count() {
+    // 残りの地雷の数から解ける
+    let game = board_with_mines(2, 4, &[(2, 0), (2, 1), (3, 0), (3, 1)]);
+    assert!(is_solvable(&game.board, (0, 0)));
+}
+#[test]
+fn test_solver_opened_squares() {
+    let game = board_with_mines(2, 3, &[(2, 0)]);
+    *game.board.get_square((2, 1)).unwrap().is_open.borrow_mut() = true;
+    assert!(is_solvable(&game.board, (0, 0)));
+}
+#[test]
+fn test_solver_constraints() {
+    // 少しずつ作り直した条件が、盤面全体から作った条件と同じになる
+    let mut game = crate::game::Game::new_with_seed(16, 16, 3).unwrap();
+    game.set_mines(crate::game::Mines::Count(40)).unwrap();
+    game.cursor((8, 8));
+    game.open();
+    let mut solver = Solver::new(&game.board);
+    loop {
+        let mut constraints = BTreeMap::new();
+        for y in 0..16 {
+            for x in 0..16 {
+                if let Some(constraint) = solver.constraint_of((x, y)) {
+                    constraints.insert((x, y), constraint);
+                }
+            }
+        }
+        assert_eq!(solver.constraints, constraints);
+        let unknowns = solver.cells.iter().flatten().filter(|&&c| c == Cell::Unknown).count();
+        assert_eq!(solver.number_of_unknowns, unknowns);
+        if !solver.step() {
+            break;
+        }
+    }
+}
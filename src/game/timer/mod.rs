@@ -0,0 +1,85 @@
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+// 止めている間の時間を含まない時計
+// Instantは単調増加するので、システムの時刻を変えても影響しない
+#[derive(Debug, Clone)]
+pub struct Timer {
+    // 動いているときは最後に動かし始めた時刻
+    started_at: Option<Instant>,
+    // 最後に止めるまでに経過した時間の合計
+    elapsed: Duration,
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer {
+            started_at: None,
+            elapsed: Duration::from_secs(0),
+        }
+    }
+
+    pub fn start(&mut self) {
+        if self.started_at.is_none() {
+            self.started_at = Some(Instant::now());
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(started_at) = self.started_at.take() {
+            self.elapsed += started_at.elapsed();
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    pub fn get_elapsed(&self) -> Duration {
+        match self.started_at {
+            Some(started_at) => self.elapsed + started_at.elapsed(),
+            None => self.elapsed
+        }
+    }
+}
+
+// mm:ss。１時間を超えたらh:mm:ss
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 * 60 {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    } else {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    }
+}
+
+#[test]
+fn test_timer() {
+    let mut timer = Timer::new();
+    assert!(!timer.is_running());
+    assert_eq!(timer.get_elapsed(), Duration::from_secs(0));
+
+    timer.start();
+    assert!(timer.is_running());
+    std::thread::sleep(Duration::from_millis(20));
+    timer.stop();
+    let elapsed = timer.get_elapsed();
+    assert!(elapsed >= Duration::from_millis(20));
+
+    // 止めている間は進まない
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(timer.get_elapsed(), elapsed);
+
+    timer.start();
+    timer.start();
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(timer.get_elapsed() >= elapsed + Duration::from_millis(20));
+}
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(Duration::from_secs(0)), "00:00");
+    assert_eq!(format_duration(Duration::from_millis(59999)), "00:59");
+    assert_eq!(format_duration(Duration::from_secs(754)), "12:34");
+    assert_eq!(format_duration(Duration::from_secs(3600 + 62)), "1:01:02");
+}
@@ -0,0 +1,279 @@
+#![allow(dead_code)]
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::screen::key::Key;
+
+// キーで行う操作
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Action {
+    CursorLeft,
+    CursorRight,
+    CursorUp,
+    CursorDown,
+    CursorHome,
+    CursorEnd,
+    CursorTop,
+    CursorBottom,
+    PageLeft,
+    PageRight,
+    PageUp,
+    PageDown,
+    Open,
+    Chord,
+    OpenAll,
+    ToggleFlag,
+    Back,
+    Forward,
+    Pause,
+    Checkpoint,
+    ListHistory,
+    Jump,
+    Redraw,
+    Help,
+    Quit,
+}
+
+// (設定ファイルでの名前, ヘルプの説明)
+const ACTIONS: [(Action, &str, &str); 25] = [
+    (Action::CursorLeft, "cursor-left", "move left"),
+    (Action::CursorRight, "cursor-right", "move right"),
+    (Action::CursorUp, "cursor-up", "move up"),
+    (Action::CursorDown, "cursor-down", "move down"),
+    (Action::CursorHome, "cursor-home", "move to the left edge"),
+    (Action::CursorEnd, "cursor-end", "move to the right edge"),
+    (Action::CursorTop, "cursor-top", "move to the top edge"),
+    (Action::CursorBottom, "cursor-bottom", "move to the bottom edge"),
+    (Action::PageLeft, "page-left", "scroll a page left"),
+    (Action::PageRight, "page-right", "scroll a page right"),
+    (Action::PageUp, "page-up", "scroll a page up"),
+    (Action::PageDown, "page-down", "scroll a page down"),
+    (Action::Open, "open", "open the square"),
+    (Action::Chord, "chord", "open the squares around"),
+    (Action::OpenAll, "open-all", "open all squares"),
+    (Action::ToggleFlag, "toggle-flag", "put or remove a flag"),
+    (Action::Back, "back", "undo"),
+    (Action::Forward, "forward", "redo"),
+    (Action::Pause, "pause", "pause or resume"),
+    (Action::Checkpoint, "checkpoint", "name the current state"),
+    (Action::ListHistory, "list-history", "list branches and checkpoints"),
+    (Action::Jump, "jump", "jump to a checkpoint or branch"),
+    (Action::Redraw, "redraw", "redraw the screen"),
+    (Action::Help, "help", "show this help"),
+    (Action::Quit, "quit", "quit"),
+];
+
+impl Action {
+    pub fn all() -> Vec<Action> {
+        ACTIONS.iter().map(|(action, _, _)| *action).collect()
+    }
+
+    pub fn get_description(self) -> &'static str {
+        ACTIONS.iter().find(|(action, _, _)| *action == self).unwrap().2
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", ACTIONS.iter().find(|(action, _, _)| action == self).unwrap().1)
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Action, String> {
+        match ACTIONS.iter().find(|(_, name, _)| *name == s) {
+            Some((action, _, _)) => Ok(*action),
+            None => Err(format!("unknown action '{}'", s))
+        }
+    }
+}
+
+// 組み込みのキー配置
+// 今までのn/o/r/iの配置がcolemak
+const PRESETS: [(&str, &[(&str, Action)]); 4] = [
+    ("colemak", &[
+        ("n", Action::CursorLeft), ("o", Action::CursorRight),
+        ("r", Action::CursorUp), ("i", Action::CursorDown),
+        ("N", Action::CursorHome), ("O", Action::CursorEnd),
+        ("R", Action::CursorTop), ("I", Action::CursorBottom),
+        ("left", Action::CursorLeft), ("right", Action::CursorRight),
+        ("up", Action::CursorUp), ("down", Action::CursorDown),
+        ("home", Action::CursorHome), ("end", Action::CursorEnd),
+        ("ctrl-home", Action::CursorTop), ("ctrl-end", Action::CursorBottom),
+        ("<", Action::PageLeft), (">", Action::PageRight),
+        ("pageup", Action::PageUp), ("pagedown", Action::PageDown),
+        ("e", Action::Open), ("enter", Action::Open),
+        ("s", Action::Chord), ("E", Action::OpenAll), ("t", Action::ToggleFlag),
+        ("b", Action::Back), ("f", Action::Forward), ("p", Action::Pause),
+        ("c", Action::Checkpoint), ("l", Action::ListHistory), ("j", Action::Jump),
+        ("ctrl-l", Action::Redraw), ("?", Action::Help), ("q", Action::Quit),
+    ]),
+    ("vim", &[
+        ("h", Action::CursorLeft), ("l", Action::CursorRight),
+        ("k", Action::CursorUp), ("j", Action::CursorDown),
+        ("0", Action::CursorHome), ("$", Action::CursorEnd),
+        ("g", Action::CursorTop), ("G", Action::CursorBottom),
+        ("<", Action::PageLeft), (">", Action::PageRight),
+        ("ctrl-b", Action::PageUp), ("ctrl-f", Action::PageDown),
+        ("space", Action::Open), ("enter", Action::Open),
+        ("d", Action::Chord), ("E", Action::OpenAll), ("m", Action::ToggleFlag),
+        ("u", Action::Back), ("ctrl-r", Action::Forward), ("p", Action::Pause),
+        ("C", Action::Checkpoint), ("L", Action::ListHistory), ("J", Action::Jump),
+        ("ctrl-l", Action::Redraw), ("?", Action::Help), ("q", Action::Quit),
+    ]),
+    ("wasd", &[
+        ("a", Action::CursorLeft), ("d", Action::CursorRight),
+        ("w", Action::CursorUp), ("s", Action::CursorDown),
+        ("A", Action::CursorHome), ("D", Action::CursorEnd),
+        ("W", Action::CursorTop), ("S", Action::CursorBottom),
+        ("<", Action::PageLeft), (">", Action::PageRight),
+        ("pageup", Action::PageUp), ("pagedown", Action::PageDown),
+        ("e", Action::Open), ("space", Action::Open),
+        ("r", Action::Chord), ("E", Action::OpenAll), ("f", Action::ToggleFlag),
+        ("z", Action::Back), ("x", Action::Forward), ("p", Action::Pause),
+        ("k", Action::Checkpoint), ("l", Action::ListHistory), ("j", Action::Jump),
+        ("ctrl-l", Action::Redraw), ("?", Action::Help), ("q", Action::Quit),
+    ]),
+    ("arrows", &[
+        ("left", Action::CursorLeft), ("right", Action::CursorRight),
+        ("up", Action::CursorUp), ("down", Action::CursorDown),
+        ("home", Action::CursorHome), ("end", Action::CursorEnd),
+        ("ctrl-home", Action::CursorTop), ("ctrl-end", Action::CursorBottom),
+        ("ctrl-left", Action::PageLeft), ("ctrl-right", Action::PageRight),
+        ("pageup", Action::PageUp), ("pagedown", Action::PageDown),
+        ("enter", Action::Open), ("a", Action::Chord), ("E", Action::OpenAll),
+        ("space", Action::ToggleFlag),
+        ("u", Action::Back), ("r", Action::Forward), ("p", Action::Pause),
+        ("c", Action::Checkpoint), ("l", Action::ListHistory), ("j", Action::Jump),
+        ("ctrl-l", Action::Redraw), ("?", Action::Help), ("q", Action::Quit),
+    ]),
+];
+
+pub const DEFAULT_PRESET: &str = "colemak";
+
+// キーと操作の対応
+// ヘルプでキーを並べる順番を保つためにVecで持つ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    bindings: Vec<(Key, Action)>,
+}
+
+impl Keymap {
+    pub fn new() -> Keymap {
+        Keymap { bindings: Vec::new() }
+    }
+
+    pub fn preset(name: &str) -> Result<Keymap, String> {
+        let bindings = match PRESETS.iter().find(|(preset, _)| *preset == name) {
+            Some((_, bindings)) => bindings,
+            None => return Err(format!("unknown keymap '{}' (available: {})",
+                                       name, Keymap::preset_names().join(", ")))
+        };
+
+        let mut keymap = Keymap::new();
+        for (key, action) in bindings.iter() {
+            keymap.bind(Key::from_str(key)?, *action);
+        }
+        Ok(keymap)
+    }
+
+    pub fn preset_names() -> Vec<&'static str> {
+        PRESETS.iter().map(|(name, _)| *name).collect()
+    }
+
+    // 既にほかの操作に割り当てられているキーなら置き換える
+    pub fn bind(&mut self, key: Key, action: Action) {
+        self.unbind(key);
+        self.bindings.push((key, action));
+    }
+
+    pub fn unbind(&mut self, key: Key) {
+        self.bindings.retain(|(bound, _)| *bound != key);
+    }
+
+    pub fn get_action(&self, key: Key) -> Option<Action> {
+        self.bindings.iter()
+            .find(|(bound, _)| *bound == key)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn get_keys(&self, action: Action) -> Vec<Key> {
+        self.bindings.iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    // メッセージに表示するキーの名前。割り当てがなければ"-"
+    pub fn key_name(&self, action: Action) -> String {
+        match self.get_keys(action).first() {
+            Some(key) => key.to_string(),
+            None => "-".to_string()
+        }
+    }
+
+    // 割り当てられたキーの一覧
+    pub fn help_lines(&self) -> Vec<String> {
+        let width = ACTIONS.iter().map(|(_, _, description)| description.len()).max().unwrap();
+        Action::all().into_iter()
+            .map(|action| {
+                let keys: Vec<String> = self.get_keys(action).iter()
+                    .map(|key| key.to_string())
+                    .collect();
+                let keys = if keys.is_empty() { "(unbound)".to_string() } else { keys.join(" ") };
+                format!("{:<width$}  {}", action.get_description(), keys, width = width)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_action_from_str() {
+    for action in Action::all() {
+        assert_eq!(Action::from_str(&action.to_string()), Ok(action));
+    }
+    assert!(Action::from_str("fly").is_err());
+}
+#[test]
+fn test_keymap_presets() {
+    // どのプリセットも同じキーを２回割り当てず、すべての操作にキーがある
+    for name in Keymap::preset_names() {
+        let (_, bindings) = PRESETS.iter().find(|(preset, _)| *preset == name).unwrap();
+        let keymap = Keymap::preset(name).unwrap();
+        assert_eq!(keymap.bindings.len(), bindings.len(), "{}", name);
+        for action in Action::all() {
+            assert!(!keymap.get_keys(action).is_empty(), "{} {}", name, action);
+        }
+    }
+    assert!(Keymap::preset("dvorak").is_err());
+}
+#[test]
+fn test_keymap_bind() {
+    let mut keymap = Keymap::preset(DEFAULT_PRESET).unwrap();
+    assert_eq!(keymap.get_action(Key::char('n')), Some(Action::CursorLeft));
+    assert_eq!(keymap.get_action(Key::char('h')), None);
+
+    keymap.bind(Key::char('n'), Action::Open);
+    assert_eq!(keymap.get_action(Key::char('n')), Some(Action::Open));
+    assert_eq!(keymap.key_name(Action::CursorLeft), "left");
+    assert_eq!(keymap.key_name(Action::Open), "e");
+
+    keymap.unbind(Key::char('n'));
+    assert_eq!(keymap.get_action(Key::char('n')), None);
+    keymap.unbind(Key::char('q'));
+    assert_eq!(keymap.key_name(Action::Quit), "-");
+}
+#[test]
+fn test_keymap_help_lines() {
+    let mut keymap = Keymap::preset("vim").unwrap();
+    keymap.unbind(Key::char('q'));
+    let lines = keymap.help_lines();
+    assert_eq!(lines.len(), Action::all().len());
+    assert!(lines[0].starts_with("move left"));
+    assert!(lines[0].ends_with("  h"));
+    assert!(lines.iter().any(|line| line.ends_with("  space enter")));
+    assert!(lines.last().unwrap().ends_with("  (unbound)"));
+}
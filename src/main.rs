@@ -1,112 +1,704 @@
 use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 extern crate libc;
 
 mod screen;
 mod game;
+mod keymap;
+mod config;
+mod repl;
+mod theme;
 
-use screen::Screen;
-use game::Game;
-use std::os::raw::c_ushort;
+use screen::{strip_color_escape_sequences, Screen};
+use screen::backend::Backend;
+use screen::backend::terminal::TerminalBackend;
+use screen::termios::TermiosError;
+use screen::key::{Event, Key, MouseButton, MouseKind};
+use screen::viewport::{Direction, ScrollIndicators, Viewport};
+use game::{coordinate, FirstClick, Game, GameStatus, Mines};
+use keymap::{Action, Keymap};
+use config::Config;
+use std::path::PathBuf;
 
+// 盤面の上に表示するスコアとシード値
+// 列の名前と行の番号を付けているときはカーソルの座標も表示する
+fn top_bar(game: &Game, is_rulers: bool) -> String {
+    let score = game.get_score().to_string_with_theme(game.get_theme());
+    if is_rulers {
+        return format!("{}  |  {}  |  seed {}", score,
+                       coordinate::position_name(game.get_cursor()), game.get_seed());
+    }
+    format!("{}  |  seed {}", score, game.get_seed())
+}
 
-#[repr(C)]
-struct Winsize {
-    ws_row: c_ushort,
-    ws_col: c_ushort,
-    ws_xpixel: c_ushort,
-    ws_ypixel: c_ushort,
+// 入力を待つ間隔。この間隔で時計の表示や自動の一時停止を確かめる
+const TICK: Duration = Duration::from_millis(100);
 
+// 一時停止中とゲーム終了時に盤面の下に表示するメッセージ
+fn status_message(game: &Game, keymap: &Keymap) -> String {
+    let quit = format!("{}: quit", keymap.key_name(Action::Quit));
+    if game.is_paused() {
+        return format!("\x1b[93mPAUSED\x1b[0m  |  {}: resume  {}",
+                       keymap.key_name(Action::Pause), quit);
+    }
+    let history = format!("{}: back  {}: forward  {}: jump",
+                          keymap.key_name(Action::Back),
+                          keymap.key_name(Action::Forward),
+                          keymap.key_name(Action::Jump));
+    match game.get_status() {
+        GameStatus::Won => format!("\x1b[93mCLEAR!\x1b[0m  |  {}  {}", history, quit),
+        GameStatus::Lost(_) => format!("\x1b[91mGAME OVER\x1b[0m  |  {}  {}", history, quit),
+        GameStatus::NotStarted | GameStatus::Playing => String::new(),
+    }
 }
 
-fn get_terminal_width() -> usize{
-    let w = Winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
-    let r = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &w) };
+// 盤面の代わりに表示するキーの一覧。中央に寄せても列がそろうように幅を合わせる
+fn help_board(keymap: &Keymap) -> String {
+    let lines = keymap.help_lines();
+    let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    lines.iter()
+        .map(|line| format!("{:<width$}", line, width = width))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// 履歴の枝とチェックポイントの一覧。枝は(手数)、*は進めたときにたどる枝
+fn history_message(game: &Game) -> String {
+    let branches: Vec<String> = game.get_branches().iter().enumerate()
+        .map(|(i, branch)| format!("{}{}({})",
+                                   if branch.is_current { "*" } else { "" },
+                                   i, branch.moves))
+        .collect();
+    let checkpoints: Vec<String> = game.get_checkpoints().iter()
+        .map(|(name, moves)| format!("{}({})", name, moves))
+        .collect();
 
-    w.ws_col as usize
+    format!("branches: {}  |  checkpoints: {}", branches.join(" "), checkpoints.join(" "))
 }
 
+const USAGE: &str = "Usage: command [--first-click <safe|opening|none>] [--no-guess] \
+                     [--open-chord] [--seed <seed>] [--idle-pause <seconds>] \
+                     [--keymap <colemak|vim|wasd|arrows>] [--config <path>] \
+                     [--theme <full-width|classic|emoji|box-drawing>] [--rulers] [--repl] \
+                     <height> <width> <mines | density%>";
+
 fn main() {
+    let mut first_click = FirstClick::Safe;
+    let mut is_no_guess = false;
+    let mut is_open_chord = false;
+    let mut seed = None;
+    // --idle-pauseで指定した時間操作がなければ一時停止する。指定しなければ一時停止しない
+    let mut idle_pause = None;
+    let mut keymap_name = None;
+    let mut theme_name = None;
+    let mut config_path = None;
+    let mut is_repl = false;
+    let mut is_rulers = false;
+    let mut positional_args = Vec::new();
     let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--first-click" => match args.next() {
+                Some(arg) => match FirstClick::from_str(arg.as_str()) {
+                    Ok(mode) => first_click = mode,
+                    Err(e) => return println!("Error: {}", e)
+                },
+                None => return println!("{}", USAGE)
+            },
+            "--no-guess" => is_no_guess = true,
+            "--open-chord" => is_open_chord = true,
+            "--seed" => match args.next() {
+                Some(arg) => match u64::from_str(arg.as_str()) {
+                    Ok(n) => seed = Some(n),
+                    Err(_) => return println!("Error: seed cannot convert to u64")
+                },
+                None => return println!("{}", USAGE)
+            },
+            "--idle-pause" => match args.next() {
+                Some(arg) => match u64::from_str(arg.as_str()) {
+                    Ok(0) => idle_pause = None,
+                    Ok(n) => idle_pause = Some(Duration::from_secs(n)),
+                    Err(_) => return println!("Error: idle-pause cannot convert to u64")
+                },
+                None => return println!("{}", USAGE)
+            },
+            "--keymap" => match args.next() {
+                Some(arg) => keymap_name = Some(arg),
+                None => return println!("{}", USAGE)
+            },
+            "--theme" => match args.next() {
+                Some(arg) => theme_name = Some(arg),
+                None => return println!("{}", USAGE)
+            },
+            "--config" => match args.next() {
+                Some(arg) => config_path = Some(PathBuf::from(arg)),
+                None => return println!("{}", USAGE)
+            },
+            "--repl" => is_repl = true,
+            "--rulers" => is_rulers = true,
+            _ => positional_args.push(arg)
+        }
+    }
+
+    // 指定がなければ既定の場所にあるときだけ読む
+    let config_path = config_path.or_else(|| Config::default_path().filter(|path| path.exists()));
+    let config = match &config_path {
+        Some(path) => Config::load(path),
+        None => Ok(Config::new())
+    };
+    let mut config = match config {
+        Ok(config) => config,
+        Err(e) => return println!("Error: {}", e)
+    };
+    if let Some(name) = &keymap_name {
+        config.set_keymap(name);
+    }
+    let keymap = match config.get_keymap() {
+        Ok(keymap) => keymap,
+        // --keymapで変えていなければ、設定ファイルの配置の名前が違う
+        Err(e) => return match (&config_path, keymap_name) {
+            (Some(path), None) => println!("Error: {}: {}", path.display(), e),
+            _ => println!("Error: {}", e)
+        }
+    };
+    // 組み込みの配置の操作を置き換えていても遊べるので、知らせるだけにする
+    if let (Some(path), Ok(warnings)) = (&config_path, config.get_keymap_warnings()) {
+        for warning in warnings {
+            eprintln!("Warning: {}: {}", path.display(), warning);
+        }
+    }
+    if let Some(name) = theme_name {
+        config.set_theme(&name);
+    }
+    let theme = match config.get_theme() {
+        Ok(theme) => theme,
+        Err(e) => return println!("Error: {}", e)
+    };
+
+    let mut args = positional_args.into_iter();
     let height = match args.next() {
         Some(arg) => match usize::from_str(arg.as_str()) {
             Ok(height) => height,
             Err(_) => return println!("Error: height cannot convert to usize")
         },
-        None => return println!("Usage: command <height> <width> <mines>")
+        None => return println!("{}", USAGE)
     };
     let width = match args.next() {
         Some(arg) => match usize::from_str(arg.as_str()) {
             Ok(width) => width,
             Err(_) => return println!("Error: width cannot convert to usize")
         },
-        None => return println!("Usage: command <height> <width> <mines>")
+        None => return println!("{}", USAGE)
     };
     let mines = match args.next() {
-        Some(arg) => match usize::from_str(arg.as_str()) {
+        Some(arg) => match Mines::from_str(arg.as_str()) {
             Ok(mines) => mines,
-            Err(_) => return println!("Error: mines cannot convert to usize")
+            Err(e) => return println!("Error: {}", e)
         },
-        None => return println!("Usage: command <height> <width> <mines>")
+        None => return println!("{}", USAGE)
     };
 
-    let mut game = match Game::new(height, width) {
+    let game = match seed {
+        Some(seed) => Game::new_with_seed(height, width, seed),
+        None => Game::new(height, width)
+    };
+    let mut game = match game {
         Ok(game) => game,
         Err(e) => return println!("Error: {}", e)
     };
-    let terminal_width = get_terminal_width();
-    //println!("{}", terminal_width);
-    //return;
-    let mut screen = Screen::new_with_terminal_width(terminal_width);
-    //let mut screen = Screen::new_debug_mode();
+    game.set_first_click(first_click);
+    game.set_no_guess(is_no_guess);
+    game.set_open_chord(is_open_chord);
+    game.set_theme(theme);
+    if let Err(e) = game.set_mines(mines) {
+        return println!("Error: {}", e);
+    }
+
+    // 座標のコマンドで遊ぶ
+    if is_repl {
+        let stdin = io::stdin();
+        if let Err(e) = repl::run(&mut game, stdin.lock(), &mut io::stdout()) {
+            println!("Error: {}", e);
+        }
+        return;
+    }
 
-    game.set_mines((height * width) / mines);
-    screen.set_board(game.board_to_string());
-    screen.set_top_bar(game.get_score().to_string());
+    // 端末でなければ行単位のモードで遊ぶ。端末の設定に失敗したときは終了する
+    match TerminalBackend::new() {
+        Ok(backend) => {
+            let mut screen = Screen::with_backend(backend);
+            run(&mut game, &mut screen, &keymap, idle_pause, is_rulers);
+        },
+        Err(TermiosError::NotATerminal) => {
+            let stdin = io::stdin();
+            if let Err(e) = run_lines(&mut game, &keymap, stdin.lock(), &mut io::stdout()) {
+                println!("Error: {}", e);
+            }
+        },
+        Err(e) => {
+            println!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
 
+// 一時停止中とゲームが終わった後に受け付ける操作ならtrue
+fn is_allowed(game: &Game, action: Action) -> bool {
+    // 一時停止中は再開と終了以外の入力は受け付けない
+    if game.is_paused() {
+        return matches!(action, Action::Pause | Action::Redraw | Action::Help | Action::Quit);
+    }
+    // ゲームが終わったら、履歴の操作と終了以外の入力は受け付けない
+    if game.get_status().is_over() {
+        return matches!(action, Action::Back | Action::Forward | Action::Checkpoint
+                        | Action::ListHistory | Action::Jump | Action::Redraw | Action::Help
+                        | Action::Quit);
+    }
+    true
+}
+
+// 盤面を操作する入力ならgameに反映してtrue。画面を使う入力ならfalse
+fn apply_action(game: &mut Game, action: Action, viewport: &mut Viewport) -> bool {
+    match action {
+        Action::CursorLeft => game.cursor_left(),
+        Action::CursorRight => game.cursor_right(),
+        Action::CursorUp => game.cursor_up(),
+        Action::CursorDown => game.cursor_down(),
+        Action::CursorHome => game.cursor_home(),
+        Action::CursorEnd => game.cursor_end(),
+        Action::CursorTop => game.cursor_top(),
+        Action::CursorBottom => game.cursor_bottom(),
+        Action::PageLeft => game.cursor(viewport.page(Direction::Left, game.get_cursor())),
+        Action::PageRight => game.cursor(viewport.page(Direction::Right, game.get_cursor())),
+        Action::PageUp => game.cursor(viewport.page(Direction::Up, game.get_cursor())),
+        Action::PageDown => game.cursor(viewport.page(Direction::Down, game.get_cursor())),
+        Action::Open => game.open(), // マスを開ける
+        Action::Chord => game.chord(), // 周りのマスをまとめて開ける
+        Action::OpenAll => game.open_all_squares(), // すべてのマスを開ける
+        Action::ToggleFlag => game.toggle_flag(), // フラッグの付け外し
+        Action::Back => game.back_history(), // １つ前の状態に戻す
+        Action::Forward => game.forward_history(), // 戻した状態を１つ進める
+        Action::Pause => game.toggle_pause(), // 一時停止と再開
+        _ => return false
+    }
+    true
+}
+
+// チェックポイントか、番号なら枝に移る
+fn jump(game: &mut Game, target: &str) -> Result<(), String> {
+    match (game.jump_to_checkpoint(target), usize::from_str(target)) {
+        (Err(_), Ok(index)) => game.jump_to_branch(index),
+        (result, _) => result
+    }
+}
+
+// qが押されるまで入力を読んで画面を描く
+// is_rulersなら盤面に列の名前と行の番号を付ける
+fn run<B: Backend>(game: &mut Game, screen: &mut Screen<B>, keymap: &Keymap,
+                   idle_pause: Option<Duration>, is_rulers: bool) {
+    let mut message = None;
+    let mut is_help = false;
+    let mut viewport = Viewport::new(game.get_board_size());
+    // 最後に描画した(上のバー, 盤面, 下のバー, スクロールの印)
+    let mut last_frame: (String, String, String, ScrollIndicators) = Default::default();
+    let mut last_input = Instant::now();
+    if is_rulers {
+        screen.set_ruler_size(game.get_ruler_size());
+    }
     loop {
-        screen.set_board(game.board_to_string());
-        screen.print();
-        match screen.read_key() {
-            'n' => game.cursor_left(),
-            'o' => game.cursor_right(),
-            'r' => game.cursor_up(),
-            'i' => game.cursor_down(),
-            'N' => game.cursor_home(),
-            'O' => game.cursor_end(),
-            'R' => game.cursor_top(),
-            'I' => game.cursor_bottom(),
-            'e' => {
-                game.open(); // マスを開ける
-                screen.set_top_bar(game.get_score().to_string());
-            },
-            'E' => {
-                game.open_all_squares(); // すべてのマスを開ける
-                screen.set_top_bar(game.get_score().to_string());
+        if screen.poll_resize() {
+            last_frame = Default::default();
+        }
+        if let Some(e) = game.take_error() {
+            message = Some(format!("\x1b[91mError\x1b[0m: {}", e));
+        }
+        // 盤面のうち端末に収まる範囲だけを表示する
+        let top_bar = top_bar(game, is_rulers);
+        screen.set_top_bar(top_bar.clone());
+        if let Some(capacity) = screen.get_board_capacity() {
+            viewport.resize(capacity);
+        }
+        viewport.follow(game.get_cursor());
+
+        // 一時停止中は盤面を隠す
+        let (board, scroll_indicators) = if is_help {
+            (help_board(keymap), ScrollIndicators::default())
+        } else if game.is_paused() {
+            (String::new(), ScrollIndicators::default())
+        } else {
+            let board = if is_rulers {
+                game.board_window_to_string_with_rulers(viewport.get_origin(), viewport.get_size())
+            } else {
+                game.board_window_to_string(viewport.get_origin(), viewport.get_size())
+            };
+            (board, viewport.get_scroll_indicators())
+        };
+        let bottom_bar = match &message {
+            Some(message) => message.clone(),
+            None if is_help => "press any key to close".to_string(),
+            None => status_message(game, keymap)
+        };
+        let frame = (top_bar, board, bottom_bar, scroll_indicators);
+        // 表示が変わったときだけ描画し直す
+        if frame != last_frame {
+            screen.set_scroll_indicators(frame.3);
+            screen.set_board(frame.1.clone());
+            screen.set_bottom_bar(frame.2.clone());
+            screen.print();
+            last_frame = frame;
+        }
+
+        let event = match screen.poll_event(TICK) {
+            Ok(Some(event)) => event,
+            // 入力が閉じられたら、それ以上は遊べないので終わる
+            Err(_) => break,
+            Ok(None) => {
+                // しばらく操作がなければ一時停止する
+                let is_idle = match idle_pause {
+                    Some(idle_pause) => last_input.elapsed() >= idle_pause,
+                    None => false
+                };
+                if is_idle && game.is_timer_running() {
+                    game.toggle_pause();
+                }
+                continue
             }
-            't' => {
-                game.toggle_flag(); // フラッグの付け外し
-                screen.set_top_bar(game.get_score().to_string());
-            },
-            'b' => {
-                game.back_history(); // １つ前の状態に戻す
-                screen.set_top_bar(game.get_score().to_string());
+        };
+        last_input = Instant::now();
+        message = None;
+
+        let key = match event {
+            Event::Key(key) => key,
+            Event::Mouse(mouse) => {
+                // 一時停止中とゲームが終わった後は、マウスでは操作しない
+                if mouse.kind != MouseKind::Press || game.is_locked() || is_help {
+                    continue;
+                }
+                if let Some(pos) = screen.square_at(mouse.col, mouse.row) {
+                    let origin = viewport.get_origin();
+                    game.cursor((origin.0 + pos.0, origin.1 + pos.1));
+                    match mouse.button {
+                        MouseButton::Left => game.open(),
+                        MouseButton::Right => game.toggle_flag(),
+                        MouseButton::Middle => game.chord(),
+                        _ => ()
+                    }
+                }
+                continue;
+            }
+        };
+
+        // ヘルプはどのキーでも閉じる
+        if is_help {
+            is_help = false;
+            continue;
+        }
+        let action = match keymap.get_action(key) {
+            Some(action) => action,
+            None => continue
+        };
+
+        if !is_allowed(game, action) || apply_action(game, action, &mut viewport) {
+            continue;
+        }
+
+        match action {
+            Action::Checkpoint => {
+                // 今の状態に名前をつける
+                if let Ok(Some(name)) = screen.read_line("checkpoint name: ") {
+                    if !name.is_empty() {
+                        game.add_checkpoint(&name);
+                    }
+                }
+                last_frame = Default::default();
+            }
+            Action::ListHistory => message = Some(history_message(game)), // 枝とチェックポイントの一覧
+            Action::Jump => {
+                // チェックポイントか枝に移る
+                if let Ok(Some(target)) = screen.read_line("jump to (checkpoint or branch): ") {
+                    if let Err(e) = jump(game, &target) {
+                        message = Some(format!("\x1b[91mError\x1b[0m: {}", e));
+                    }
+                }
+                last_frame = Default::default();
+            }
+            Action::Redraw => {
+                // 画面全体を描き直す
+                screen.redraw();
+                last_frame = Default::default();
             }
-            'q' => break,
+            Action::Help => is_help = true, // キーの一覧
+            Action::Quit => break,
             _ => ()
         }
     }
 }
 
+// 端末が使えないときに、標準入力から１行ずつ読んで遊ぶ
+// １行には空白で区切ったキーの名前を書く。例: "o o e", "down t"
+// checkpointとjumpのキーには、続けて名前を書く
+// 行を読むたびに、エスケープシーケンスを使わずに画面を文字だけで書く
+fn run_lines<R: BufRead, W: Write>(game: &mut Game, keymap: &Keymap, input: R, output: &mut W)
+    -> io::Result<()> {
+    // 盤面はいつも全体を表示する
+    let mut viewport = Viewport::new(game.get_board_size());
+    write_plain_screen(output, game, keymap, None)?;
+
+    for line in input.lines() {
+        let line = line?;
+        let mut message = None;
+        let mut tokens = line.split_whitespace();
+        while let Some(token) = tokens.next() {
+            let action = match Key::from_str(token) {
+                Ok(key) => keymap.get_action(key),
+                Err(e) => {
+                    message = Some(format!("Error: {}", e));
+                    break;
+                }
+            };
+            let action = match action {
+                Some(action) => action,
+                None => {
+                    message = Some(format!("Error: key '{}' is not bound", token));
+                    break;
+                }
+            };
+            if !is_allowed(game, action) || apply_action(game, action, &mut viewport) {
+                continue;
+            }
+
+            match action {
+                Action::Checkpoint => match tokens.next() {
+                    Some(name) => game.add_checkpoint(name),
+                    None => message = Some("Error: checkpoint name is missing".to_string())
+                },
+                Action::ListHistory => message = Some(history_message(game)),
+                Action::Jump => {
+                    let result = tokens.next()
+                        .ok_or_else(|| "checkpoint or branch is missing".to_string())
+                        .and_then(|target| jump(game, target));
+                    if let Err(e) = result {
+                        message = Some(format!("Error: {}", e));
+                    }
+                },
+                Action::Help => message = Some(help_board(keymap)),
+                Action::Quit => return Ok(()),
+                _ => ()
+            }
+        }
+        if let Some(e) = game.take_error() {
+            message = Some(format!("Error: {}", e));
+        }
+        write_plain_screen(output, game, keymap, message)?;
+    }
+    Ok(())
+}
+
+// 上のバー、盤面、カーソルの位置、メッセージを文字だけで書き、空行で区切る
+fn write_plain_screen<W: Write>(output: &mut W, game: &Game, keymap: &Keymap,
+                                message: Option<String>) -> io::Result<()> {
+    let mut text = top_bar(game, false) + "\n";
+    if !game.is_paused() {
+        text += &game.board_to_string();
+        text += "\n";
+    }
+    let cursor = game.get_cursor();
+    text += &format!("cursor: {} {}\n", cursor.0, cursor.1);
+    let message = message.unwrap_or_else(|| status_message(game, keymap));
+    if !message.is_empty() {
+        text += &message;
+        text += "\n";
+    }
+    writeln!(output, "{}", strip_color_escape_sequences(&text))?;
+    output.flush()
+}
+
 //
 
+#[cfg(test)]
+use screen::backend::memory::MemoryBackend;
+
+// 開いていない５マスの行
+#[cfg(test)]
+fn board_line(left: usize) -> String {
+    format!("{}{}", " ".repeat(left), "[]".repeat(5))
+}
+
+// 5x5、地雷3つ、シード1の盤面
+// (2, 1)を開けると２になる
+#[cfg(test)]
+fn small_game() -> Game {
+    let mut game = Game::new_with_seed(5, 5, 1).unwrap();
+    game.set_mines(Mines::Count(3)).unwrap();
+    game
+}
+
+// gameをメモリ上の(行数, 列数)の端末で、themeのマスを表示して遊ぶ
+#[cfg(test)]
+fn run_in_memory(game: &mut Game, inputs: impl FnOnce(&mut MemoryBackend), size: (usize, usize),
+                 is_rulers: bool, theme: &str) -> MemoryBackend {
+    game.set_theme(theme::Theme::preset(theme).unwrap());
+    let mut backend = MemoryBackend::new(size.0, size.1);
+    inputs(&mut backend);
+
+    let mut screen = Screen::with_backend(backend);
+    let keymap = Keymap::preset(keymap::DEFAULT_PRESET).unwrap();
+    run(game, &mut screen, &keymap, None, is_rulers);
+
+    screen.into_backend()
+}
+
 #[test]
-#[ignore]
 fn test_main() {
-    let game = Game::new(10, 10).unwrap();
-    let mut screen = Screen::new();
+    let mut game = small_game();
+    let backend = run_in_memory(&mut game, |backend| {
+        backend.push_keys("q");
+    }, (24, 80), false, theme::DEFAULT_THEME);
+    assert_eq!(game.get_status(), GameStatus::NotStarted);
+    assert_eq!(backend.get_frames().len(), 1);
+    let lines = backend.get_last_frame().unwrap().to_lines();
+    assert!(lines[0].contains("seed 1"));
+    // 盤面は３行目から、中央に寄せて表示する
+    assert_eq!(lines[1], "");
+    for line in &lines[2..7] {
+        assert_eq!(*line, board_line(35));
+    }
+    assert_eq!(lines[7], "");
+}
+#[test]
+fn test_run_closed_input() {
+    // 入力が閉じられたら、quitを押さなくても終わる
+    let mut game = small_game();
+    run_in_memory(&mut game, |backend| {
+        backend.push_keys("ooe").push_close();
+    }, (24, 80), false, theme::DEFAULT_THEME);
+    assert_eq!(game.get_status(), GameStatus::Playing);
+    // 名前を読んでいる途中で閉じられても終わる
+    let mut game = small_game();
+    run_in_memory(&mut game, |backend| {
+        backend.push_keys("ooecab").push_close();
+    }, (24, 80), false, theme::DEFAULT_THEME);
+    assert!(game.get_checkpoints().is_empty());
+}
+#[test]
+fn test_run_open_and_flag() {
+    let mut game = small_game();
+    let backend = run_in_memory(&mut game, |backend| {
+        backend.push_keys("oo").push_bytes(b"\x1b[B").push_keys("e").push_timeout()
+            .push_bytes(b"\x1b[A").push_keys("tq");
+    }, (24, 80), false, theme::DEFAULT_THEME);
+    assert_eq!(game.get_status(), GameStatus::Playing);
+    assert_eq!(game.get_cursor(), (2, 0));
+    let lines = backend.get_last_frame().unwrap().to_lines();
+    assert_eq!(lines[2], format!("{}[][]/>[][]", " ".repeat(35)));
+    assert_eq!(lines[3], format!("{}[][]２[][]", " ".repeat(35)));
+
+    // ２回目からは変わったところだけを書く
+    let output = backend.get_output();
+    assert_eq!(output.matches("\x1b[2J").count(), 1);
+    // 変わったところだけを書いても、見える画面は最後の画面と同じ
+    let vt = backend.get_vt();
+    assert_eq!(vt.to_lines(), lines);
+    // カーソルのある旗は反転して表示する
+    let snapshot = vt.to_snapshot();
+    assert_eq!(snapshot[2], format!("{}[][]{{7;93}}/>{{}}[][]", " ".repeat(35)));
+    assert_eq!(snapshot[3], format!("{}[][]２[][]", " ".repeat(35)));
+}
+#[test]
+fn test_run_mouse() {
+    // 盤面の左上は(35, 2)。(2, 1)を左クリックで開けて、(0, 0)を右クリックで旗を立てる
+    let mut game = small_game();
+    let backend = run_in_memory(&mut game, |backend| {
+        backend.push_bytes(b"\x1b[<0;40;4M\x1b[<0;40;4m").push_bytes(b"\x1b[<2;37;3M")
+            .push_keys("q");
+    }, (24, 80), false, theme::DEFAULT_THEME);
+    assert_eq!(game.get_cursor(), (0, 0));
+    let lines = backend.get_last_frame().unwrap().to_lines();
+    assert_eq!(lines[2], format!("{}/>[][][][]", " ".repeat(35)));
+    assert_eq!(lines[3], format!("{}[][]２[][]", " ".repeat(35)));
+}
+#[test]
+fn test_run_rulers() {
+    // 盤面は名前と番号を含めて中央に寄せる。マスの左上は(36, 3)
+    let mut game = small_game();
+    let backend = run_in_memory(&mut game, |backend| {
+        backend.push_bytes(b"\x1b[<0;41;5M").push_keys("q");
+    }, (24, 80), true, theme::DEFAULT_THEME);
+    assert_eq!(game.get_cursor(), (2, 1));
+    let lines = backend.get_vt().to_lines();
+    assert!(lines[0].ends_with("  |  C2  |  seed 1"));
+    assert_eq!(lines[2], format!("{}A B C D E", " ".repeat(36)));
+    assert_eq!(lines[3], format!("{}1 [][][][][]", " ".repeat(34)));
+    assert_eq!(lines[4], format!("{}2 [][]２[][]", " ".repeat(34)));
+    assert_eq!(lines[8], "");
+}
+#[test]
+fn test_run_themes() {
+    // どのテーマでもマスは２列なので、盤面の位置もクリックするマスも変わらない
+    for name in theme::Theme::preset_names() {
+        let mut game = small_game();
+        let backend = run_in_memory(&mut game, |backend| {
+            backend.push_bytes(b"\x1b[<0;41;5M").push_bytes(b"\x1b[<2;37;4M").push_keys("q");
+        }, (24, 80), true, name);
+        assert_eq!(game.get_cursor(), (0, 0), "{}", name);
+        let theme = game.get_theme();
+        let vt = backend.get_vt();
+        assert_eq!(vt.to_lines(), backend.get_last_frame().unwrap().to_lines(), "{}", name);
+        let lines = vt.to_lines();
+        let left = " ".repeat(34);
+        let text = |glyph: &theme::Glyph| strip_color_escape_sequences(&glyph.to_string());
+        let unopened = text(theme.get_unopened());
+        assert_eq!(lines[3], format!("{}1 {}{}", left, text(theme.get_flag()),
+                                     unopened.repeat(4)).trim_end(), "{}", name);
+        assert_eq!(lines[4], format!("{}2 {}{}{}", left, unopened.repeat(2),
+                                     text(theme.get_number(2)), unopened.repeat(2)).trim_end(),
+                   "{}", name);
+        // 上のバーもテーマのグリフを使う
+        assert!(lines[0].contains(&format!("{} 1", theme.get_flag().get_text())), "{}", name);
+    }
+}
+#[test]
+fn test_run_resize_and_help() {
+    let mut game = small_game();
+    let backend = run_in_memory(&mut game, |backend| {
+        backend.push_resize(40, 60).push_keys("?");
+        backend.push_keys(" q");
+    }, (24, 80), false, theme::DEFAULT_THEME);
+    let frames = backend.get_frames();
+    assert_eq!(frames.len(), 4);
+    assert_eq!(frames[0].to_lines()[2], board_line(35));
+    // 大きさが変わったら中央に寄せ直す。上のバーは折り返して２行になる
+    assert_eq!(frames[1].get_size(), (40, 60));
+    assert_eq!(frames[1].to_lines()[3], board_line(25));
+    // ヘルプはキーの一覧を表示し、どのキーでも閉じる
+    let lines = frames[2].to_lines();
+    assert!(lines.iter().any(|line| line.trim_start().starts_with("move left")));
+    assert!(lines.iter().any(|line| line.contains("press any key to close")));
+    assert_eq!(frames[3].to_lines()[3], board_line(25));
+}
+#[test]
+fn test_run_lines() {
+    let mut game = small_game();
+    let keymap = Keymap::preset(keymap::DEFAULT_PRESET).unwrap();
+    let input = "o o down e\nxyz\nc\nq\nt\n";
+    let mut output = Vec::new();
+    run_lines(&mut game, &keymap, input.as_bytes(), &mut output).unwrap();
 
-    screen.set_board(game.board_to_string());
-    screen.print();
+    let output = String::from_utf8(output).unwrap();
+    assert!(!output.contains('\x1b'));
+    // 最初の画面と、qより前の３行を読むたびに画面を書く。qのあとは読まない
+    let screens: Vec<&str> = output.split("\n\n").filter(|s| !s.is_empty()).collect();
+    assert_eq!(screens.len(), 4);
+    let lines: Vec<&str> = screens[1].lines().collect();
+    assert!(lines[0].contains("seed 1"));
+    assert_eq!(&lines[1..6], &["[][][][][]", "[][]２[][]", "[][][][][]", "[][][][][]", "[][][][][]"]);
+    assert_eq!(lines[6], "cursor: 2 1");
+    assert!(screens[2].ends_with("Error: unknown key 'xyz'"));
+    // チェックポイントの名前がなければエラー
+    assert!(screens[3].ends_with("Error: checkpoint name is missing"));
+    assert_eq!(game.get_cursor(), (2, 1));
 }
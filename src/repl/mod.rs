@@ -0,0 +1,233 @@
+#![allow(dead_code)]
+
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use crate::game::coordinate::{self, position_name};
+use crate::game::{Game, GameStatus};
+use crate::screen::strip_color_escape_sequences;
+
+// 文字で打ち込むコマンドで遊ぶモード
+// カーソルを動かさずに座標でマスを選ぶので、画面を見なくても読み上げだけで遊べる
+//
+//     open C7
+//     flag 3 4
+//     chord B2
+//
+// 座標は"C7"のように列の名前と行の番号を続けて書くか、"3 4"や"C 7"のように列と行を分けて書く
+// 列も行も1から数える
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Command {
+    Open((usize, usize)),
+    Flag((usize, usize)),
+    Chord((usize, usize)),
+    Undo,
+    Redo,
+    Show,
+    Help,
+    Quit,
+}
+
+// (コマンドの書き方, 説明)
+const COMMANDS: [(&str, &str); 8] = [
+    ("open <square>", "open a square, e.g. open C7"),
+    ("flag <square>", "toggle a flag, e.g. flag 3 4"),
+    ("chord <square>", "open the squares around a number"),
+    ("undo", "undo the last move"),
+    ("redo", "redo an undone move"),
+    ("show", "show the board"),
+    ("help", "show this list"),
+    ("quit", "quit the game"),
+];
+
+// "C7", "3 4", "C 7"
+fn parse_square(args: &[&str]) -> Result<(usize, usize), String> {
+    match args {
+        [position] => coordinate::parse_position(position),
+        [column, row] => {
+            // 列は番号でも名前でもよい
+            let x = if column.chars().all(|c| c.is_ascii_digit()) {
+                coordinate::parse_row(column)
+            } else {
+                coordinate::parse_column(column)
+            };
+            let x = x.map_err(|_| format!("invalid column '{}'", column))?;
+            Ok((x, coordinate::parse_row(row)?))
+        },
+        _ => Err("square is missing, e.g. C7 or 3 4".to_string())
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (name.to_ascii_lowercase(), args),
+            None => return Err("empty command".to_string())
+        };
+        let command = match name.as_str() {
+            "open" | "o" => return Ok(Command::Open(parse_square(args)?)),
+            "flag" | "f" => return Ok(Command::Flag(parse_square(args)?)),
+            "chord" | "c" => return Ok(Command::Chord(parse_square(args)?)),
+            "undo" | "u" => Command::Undo,
+            "redo" | "r" => Command::Redo,
+            "show" | "s" => Command::Show,
+            "help" | "h" | "?" => Command::Help,
+            "quit" | "q" | "exit" => Command::Quit,
+            _ => return Err(format!("unknown command '{}'. type help for the list", name))
+        };
+        if !args.is_empty() {
+            return Err(format!("{} takes no arguments", name));
+        }
+        Ok(command)
+    }
+}
+
+// 列の名前を上に、行の番号を左に付けた盤面全体
+fn board_with_rulers(game: &Game) -> String {
+    let (height, width) = game.get_board_size();
+    let board = game.board_window_to_string_with_rulers((0, 0), (width, height));
+    // テーマによってはマスの右が空白になるので、行末の空白は除く
+    strip_color_escape_sequences(&board).lines()
+        .map(|line| line.trim_end())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+// 盤面の下に書く、スコアと勝ち負け
+fn status_line(game: &Game) -> String {
+    let score = strip_color_escape_sequences(&game.get_score().to_string_with_theme(game.get_theme()));
+    match game.get_status() {
+        GameStatus::Won => format!("{}  |  CLEAR!", score),
+        GameStatus::Lost(position) => format!("{}  |  GAME OVER at {}", score, position_name(position)),
+        GameStatus::NotStarted | GameStatus::Playing => score,
+    }
+}
+
+fn help_lines() -> Vec<String> {
+    let width = COMMANDS.iter().map(|(usage, _)| usage.len()).max().unwrap_or(0);
+    COMMANDS.iter()
+        .map(|(usage, description)| format!("{:<w$}  {}", usage, description, w = width))
+        .collect()
+}
+
+// 盤面の外ならエラー
+fn check_square(game: &Game, position: (usize, usize)) -> Result<(usize, usize), String> {
+    let (height, width) = game.get_board_size();
+    if width <= position.0 || height <= position.1 {
+        return Err(format!("{} is outside the board (A1 to {})",
+                           position_name(position), position_name((width - 1, height - 1))));
+    }
+    Ok(position)
+}
+
+// 盤面を操作するコマンドをgameに反映する
+fn execute(game: &mut Game, command: Command) -> Result<(), String> {
+    match command {
+        Command::Open(position) | Command::Flag(position) | Command::Chord(position) => {
+            if game.is_locked() {
+                return Err("the game is over. use undo to go back".to_string());
+            }
+            game.cursor(check_square(game, position)?);
+            match command {
+                Command::Open(_) => game.open(),
+                Command::Flag(_) => game.toggle_flag(),
+                _ => game.chord()
+            }
+        },
+        Command::Undo => game.back_history(),
+        Command::Redo => game.forward_history(),
+        Command::Show | Command::Help | Command::Quit => (),
+    }
+    match game.take_error() {
+        Some(e) => Err(e),
+        None => Ok(())
+    }
+}
+
+// quitかinputが終わるまで１行ずつコマンドを読む
+// 盤面を変えるコマンドのあとは、座標の付いた盤面を文字だけで書く
+pub fn run<R: BufRead, W: Write>(game: &mut Game, input: R, output: &mut W) -> io::Result<()> {
+    writeln!(output, "{}\n{}\ntype help for the list of commands",
+             board_with_rulers(game), status_line(game))?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match Command::from_str(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                writeln!(output, "Error: {}", e)?;
+                continue;
+            }
+        };
+        match command {
+            Command::Quit => break,
+            Command::Help => writeln!(output, "{}", help_lines().join("\n"))?,
+            command => match execute(game, command) {
+                Ok(()) => writeln!(output, "{}\n{}", board_with_rulers(game), status_line(game))?,
+                Err(e) => writeln!(output, "Error: {}", e)?
+            }
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_command_from_str() {
+    assert_eq!(Command::from_str("open C7"), Ok(Command::Open((2, 6))));
+    assert_eq!(Command::from_str("  Flag 3 4 "), Ok(Command::Flag((2, 3))));
+    assert_eq!(Command::from_str("chord b 2"), Ok(Command::Chord((1, 1))));
+    assert_eq!(Command::from_str("o aa1"), Ok(Command::Open((26, 0))));
+    assert_eq!(Command::from_str("undo"), Ok(Command::Undo));
+    assert_eq!(Command::from_str("q"), Ok(Command::Quit));
+    assert!(Command::from_str("open").is_err());
+    assert!(Command::from_str("open 0 1").is_err());
+    assert!(Command::from_str("flag C").is_err());
+    assert!(Command::from_str("undo 2").is_err());
+    assert!(Command::from_str("dig C7").is_err());
+}
+#[test]
+fn test_repl() {
+    use crate::game::Mines;
+
+    let mut game = Game::new_with_seed(5, 5, 1).unwrap();
+    game.set_mines(Mines::Count(3)).unwrap();
+    let input = "open C2\nflag F1\nflag 1 1\ndig\nundo\nhelp\nquit\nshow\n";
+    let mut output = Vec::new();
+    run(&mut game, input.as_bytes(), &mut output).unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    assert!(!output.contains('\x1b'));
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(&lines[0..3], &["  A B C D E", "1 [][][][][]", "2 [][][][][]"]);
+    assert_eq!(&lines[8..10], &["  A B C D E", "1 [][][][][]"]);
+    assert_eq!(lines[10], "2 [][]２[][]");
+    assert_eq!(lines[15], "Error: F1 is outside the board (A1 to E5)");
+    assert_eq!(lines[17], "1 />[][][][]");
+    assert!(lines[22].contains("/> 1  "));
+    assert_eq!(lines[23], "Error: unknown command 'dig'. type help for the list");
+    // undoで旗を外す
+    assert_eq!(lines[25], "1 [][][][][]");
+    assert!(lines.last().unwrap().starts_with("quit"));
+}
+#[test]
+fn test_board_with_rulers() {
+    let game = Game::new_with_seed(10, 28, 1).unwrap();
+    let board = board_with_rulers(&game);
+    let lines: Vec<&str> = board.lines().collect();
+    assert_eq!(lines.len(), 12);
+    // AAとABは縦に並べ、隣の名前とつなげない
+    assert_eq!(lines[0], format!("{}A A", " ".repeat(3 + 26 * 2)));
+    assert!(lines[1].starts_with("   A B C"));
+    assert!(lines[1].ends_with("X Y Z A B"));
+    assert_eq!(lines[2].len(), 3 + 28 * 2);
+    assert!(lines[2].starts_with(" 1 [][]"));
+    assert!(lines[11].starts_with("10 [][]"));
+}
@@ -0,0 +1,164 @@
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::io;
+use std::time::Duration;
+
+use crate::screen::backend::Backend;
+use crate::screen::frame::Frame;
+use crate::screen::vt::Vt;
+
+// テストで流し込む入力
+#[derive(Debug, PartialEq, Clone)]
+enum Input {
+    Bytes(Vec<u8>),
+    // 何も押されないまま待ち時間が過ぎる
+    Timeout,
+    // 端末の大きさが(行数, 列数)に変わる
+    Resize(usize, usize),
+    // 入力が閉じられる
+    Close,
+}
+
+// 端末の代わりにメモリに書くテスト用のバックエンド
+// 表示した画面を記録し、用意した入力を順に返す
+// 書かれた出力はVtで解釈し、実際に見える画面も再現する
+#[derive(Debug)]
+pub struct MemoryBackend {
+    size: (usize, usize),
+    inputs: VecDeque<Input>,
+    resized: Option<(usize, usize)>,
+    output: String,
+    frames: Vec<Frame>,
+    vt: Vt,
+}
+
+impl MemoryBackend {
+    pub fn new(height: usize, width: usize) -> MemoryBackend {
+        MemoryBackend {
+            size: (height, width),
+            inputs: VecDeque::new(),
+            resized: None,
+            output: String::new(),
+            frames: Vec::new(),
+            vt: Vt::new(height, width),
+        }
+    }
+
+    // １回のreadで読めるバイト列
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> &mut MemoryBackend {
+        self.inputs.push_back(Input::Bytes(bytes.to_vec()));
+        self
+    }
+
+    // 文字を１つずつ押す
+    pub fn push_keys(&mut self, keys: &str) -> &mut MemoryBackend {
+        for c in keys.chars() {
+            let mut buf = [0u8; 4];
+            self.push_bytes(c.encode_utf8(&mut buf).as_bytes());
+        }
+        self
+    }
+
+    pub fn push_timeout(&mut self) -> &mut MemoryBackend {
+        self.inputs.push_back(Input::Timeout);
+        self
+    }
+
+    pub fn push_resize(&mut self, height: usize, width: usize) -> &mut MemoryBackend {
+        self.inputs.push_back(Input::Resize(height, width));
+        self
+    }
+
+    // これより後の入力は読めない
+    pub fn push_close(&mut self) -> &mut MemoryBackend {
+        self.inputs.push_back(Input::Close);
+        self
+    }
+
+    // これまでに書かれたすべての出力
+    pub fn get_output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn get_frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn get_last_frame(&self) -> Option<&Frame> {
+        self.frames.last()
+    }
+
+    // 出力を端末で表示したときの画面
+    pub fn get_vt(&self) -> &Vt {
+        &self.vt
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn get_size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    fn poll_resize(&mut self) -> Option<(usize, usize)> {
+        self.resized.take()
+    }
+
+    fn write(&mut self, output: &str) {
+        self.output += output;
+        self.vt.feed(output);
+    }
+
+    fn present(&mut self, frame: &Frame) {
+        self.frames.push(frame.clone());
+    }
+
+    // 入力を使い切ったら、終わらないテストにならないようにpanicする
+    fn read(&mut self, _timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        match self.inputs.pop_front() {
+            Some(Input::Bytes(bytes)) => Ok(Some(bytes)),
+            Some(Input::Timeout) => Ok(None),
+            Some(Input::Resize(height, width)) => {
+                self.size = (height, width);
+                self.vt.resize(height, width);
+                self.resized = Some(self.size);
+                Ok(None)
+            },
+            Some(Input::Close) => {
+                // 閉じた後も読もうとすれば同じErrを返す
+                self.inputs.push_front(Input::Close);
+                Err(io::Error::new(io::ErrorKind::UnexpectedEof, "input is closed"))
+            },
+            None => panic!("MemoryBackend: no more scripted input")
+        }
+    }
+}
+
+#[test]
+fn test_memory_backend() {
+    let mut backend = MemoryBackend::new(3, 4);
+    backend.push_keys("aあ").push_timeout().push_resize(5, 6).push_bytes(b"\x1b[A");
+    backend.push_close();
+    assert_eq!(backend.read(Duration::from_secs(0)).unwrap(), Some(b"a".to_vec()));
+    assert_eq!(backend.read(Duration::from_secs(0)).unwrap(), Some("あ".as_bytes().to_vec()));
+    assert_eq!(backend.read(Duration::from_secs(0)).unwrap(), None);
+    assert_eq!(backend.poll_resize(), None);
+    assert_eq!(backend.read(Duration::from_secs(0)).unwrap(), None);
+    assert_eq!(backend.poll_resize(), Some((5, 6)));
+    assert_eq!(backend.get_size(), (5, 6));
+    assert_eq!(backend.read(Duration::from_secs(0)).unwrap(), Some(b"\x1b[A".to_vec()));
+    assert!(backend.read(Duration::from_secs(0)).is_err());
+    assert!(backend.read(Duration::from_secs(0)).is_err());
+
+    backend.write("ab");
+    backend.write("c");
+    assert_eq!(backend.get_output(), "abc");
+    assert_eq!(backend.get_vt().to_lines()[0], "abc");
+    backend.present(&Frame::from_text("abc", 5, 6));
+    assert_eq!(backend.get_frames().len(), 1);
+}
+#[test]
+#[should_panic]
+fn test_memory_backend_no_input() {
+    let _ = MemoryBackend::new(3, 4).read(Duration::from_secs(0));
+}
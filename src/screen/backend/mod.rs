@@ -0,0 +1,26 @@
+use std::io;
+use std::time::Duration;
+
+use crate::screen::frame::Frame;
+
+pub mod terminal;
+pub mod memory;
+
+// Screenが画面を書き、入力を読む先
+pub trait Backend {
+    // (行数, 列数)。分からなければ0
+    fn get_size(&self) -> (usize, usize);
+
+    // 大きさが変わったか、画面全体を描き直す必要があれば新しい(行数, 列数)
+    fn poll_resize(&mut self) -> Option<(usize, usize)>;
+
+    // 出力を書いてすぐに表示する
+    fn write(&mut self, output: &str);
+
+    // writeで表示し終えた画面。記録したいときだけ使う
+    fn present(&mut self, _frame: &Frame) {}
+
+    // timeoutの間に読めたバイト列。読めなければOk(None)
+    // 入力が閉じられて、もう読めなければErr
+    fn read(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>>;
+}
@@ -0,0 +1,245 @@
+use std::io::{self, Write};
+use std::os::raw::c_ushort;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+extern crate libc;
+
+use crate::screen::backend::Backend;
+use crate::screen::termios::{self, Termios, TermiosError};
+
+#[repr(C)]
+struct Winsize {
+    ws_row: c_ushort,
+    ws_col: c_ushort,
+    ws_xpixel: c_ushort,
+    ws_ypixel: c_ushort,
+
+}
+
+// (行数, 列数)
+fn get_terminal_size() -> (usize, usize) {
+    let w = Winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+    unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &w) };
+
+    (w.ws_row as usize, w.ws_col as usize)
+}
+
+// 端末の大きさが変わったらtrueになる
+static IS_RESIZED: AtomicBool = AtomicBool::new(false);
+// Ctrl-Zで止まったあと再開したらtrueになる
+static IS_CONTINUED: AtomicBool = AtomicBool::new(false);
+// 代替画面とマウスの設定を使っている間はtrue
+static IS_ENTERED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_resize(_: libc::c_int) {
+    IS_RESIZED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_continue(_: libc::c_int) {
+    IS_CONTINUED.store(true, Ordering::SeqCst);
+}
+
+// 端末を元に戻してから、本来の動作でシグナルを受け取り直す
+extern "C" fn on_terminate(signal: libc::c_int) {
+    leave_terminal();
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+// 端末を元に戻して止まり、再開したら描き直せるようにする
+extern "C" fn on_suspend(signal: libc::c_int) {
+    leave_terminal();
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        // ハンドラの中では同じシグナルが止められているので、止めないようにしてから送る
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, signal);
+        libc::sigprocmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut());
+        libc::raise(signal);
+        // ここで止まり、再開するとここから続く
+        set_handler(signal, on_suspend);
+    }
+    IS_CONTINUED.store(true, Ordering::SeqCst);
+}
+
+fn set_handler(signal: libc::c_int, handler: extern "C" fn(libc::c_int)) {
+    unsafe { libc::signal(signal, handler as libc::sighandler_t) };
+}
+
+// SIGWINCHを受け取ると入力待ちのpollが中断されるので、すぐに描画し直せる
+// 終了させるシグナルとpanicでは端末を元に戻す
+fn watch_signals() {
+    set_handler(libc::SIGWINCH, on_resize);
+    set_handler(libc::SIGCONT, on_continue);
+    set_handler(libc::SIGTSTP, on_suspend);
+    for &signal in &[libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        set_handler(signal, on_terminate);
+    }
+
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // panicのメッセージは元の画面に表示する
+        leave_terminal();
+        hook(info);
+    }));
+}
+
+// シグナルハンドラからも呼べるように、printではなくlibc::writeで書く
+fn write_stdout(output: &str) {
+    unsafe { libc::write(libc::STDOUT_FILENO, output.as_ptr() as *const libc::c_void, output.len()) };
+}
+
+fn enter_terminal() {
+    IS_ENTERED.store(true, Ordering::SeqCst);
+    write_stdout(concat!(alternate_screen!(enable), mouse_tracking!(enable)));
+}
+
+// 何度呼んでも一度だけ戻す
+fn leave_terminal() {
+    termios::restore_initial_mode();
+    if IS_ENTERED.swap(false, Ordering::SeqCst) {
+        write_stdout(concat!(mouse_tracking!(disable), hide_cursor!(disable), alternate_screen!(disable)));
+    }
+}
+
+// 標準入力が読めるようになるまでtimeoutの間待つ。読めるならtrue
+fn wait_fd(fd: libc::c_int, timeout: Duration) -> bool {
+    let mut fds = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+    let result = unsafe { libc::poll(&mut fds, 1, timeout) };
+
+    result > 0
+}
+
+// 標準入力と標準出力の端末
+pub struct TerminalBackend {
+    is_debug: bool,
+    termios: Termios,
+}
+
+impl TerminalBackend {
+    // 標準入力と標準出力のどちらかが端末でなければエラー
+    pub fn new() -> Result<TerminalBackend, TermiosError> {
+        if unsafe { libc::isatty(libc::STDOUT_FILENO) } != 1 {
+            return Err(TermiosError::NotATerminal);
+        }
+        let backend = TerminalBackend::open(false)?;
+        let _ = io::stdout().flush();
+        enter_terminal();
+        watch_signals();
+        Ok(backend)
+    }
+
+    // 代替画面を使わず、端末の大きさも調べない
+    pub fn new_debug_mode() -> Result<TerminalBackend, TermiosError> {
+        TerminalBackend::open(true)
+    }
+
+    fn open(is_debug: bool) -> Result<TerminalBackend, TermiosError> {
+        let mut termios = Termios::new()?;
+
+        let result = termios.mode_off(libc::ICANON | libc::ECHO).map(|_| ())
+            // 待つのはreadで行うので、libc::readはすぐに返るようにする
+            .and_then(|_| termios.set_read_timeout(0, 0).map(|_| ()));
+        if let Err(e) = result {
+            // 途中まで変えた設定を戻す
+            let _ = termios.set_initial_mode();
+            return Err(e);
+        }
+
+        Ok(TerminalBackend {
+            is_debug,
+            termios,
+        })
+    }
+}
+
+impl Backend for TerminalBackend {
+    fn get_size(&self) -> (usize, usize) {
+        if self.is_debug {
+            return (0, 0);
+        }
+        get_terminal_size()
+    }
+
+    fn poll_resize(&mut self) -> Option<(usize, usize)> {
+        // 止まっている間に端末の設定と画面が変えられているので、設定し直して描き直す
+        if IS_CONTINUED.swap(false, Ordering::SeqCst) {
+            let _ = self.termios.set_mode();
+            let _ = io::stdout().flush();
+            enter_terminal();
+            IS_RESIZED.store(false, Ordering::SeqCst);
+            return Some(self.get_size());
+        }
+        if IS_RESIZED.swap(false, Ordering::SeqCst) {
+            return Some(self.get_size());
+        }
+        None
+    }
+
+    fn write(&mut self, output: &str) {
+        print!("{}", output);
+        let _ = io::stdout().flush();
+    }
+
+    fn read(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        read_fd(libc::STDIN_FILENO, timeout)
+    }
+}
+
+// io::stdinはバッファに先読みしてpollで待てなくなるので、直接readする
+// 閉じられたfdはpollで読めると返り続けるので、読めたバイトが0ならErrにする
+fn read_fd(fd: libc::c_int, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+    if !wait_fd(fd, timeout) {
+        return Ok(None);
+    }
+
+    let mut buf = [0u8; 64];
+    let result = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+    if result == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "input is closed"));
+    }
+    if result < 0 {
+        let error = io::Error::last_os_error();
+        return match error.kind() {
+            io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock => Ok(None),
+            // 端末が閉じられるとEIOになる
+            _ => Err(error)
+        };
+    }
+    Ok(Some(buf[..result as usize].to_vec()))
+}
+
+impl Drop for TerminalBackend {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        leave_terminal();
+    }
+}
+
+#[test]
+fn test_read_fd_closed_pipe() {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let (reader, writer) = (fds[0], fds[1]);
+
+    assert_eq!(read_fd(reader, Duration::from_millis(0)).unwrap(), None);
+    unsafe { libc::write(writer, b"q".as_ptr() as *const libc::c_void, 1) };
+    assert_eq!(read_fd(reader, Duration::from_millis(0)).unwrap(), Some(b"q".to_vec()));
+
+    // 書く側を閉じると、待たずにErrが返る
+    unsafe { libc::close(writer) };
+    let error = read_fd(reader, Duration::from_secs(10)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(read_fd(reader, Duration::from_secs(10)).is_err());
+    unsafe { libc::close(reader) };
+}
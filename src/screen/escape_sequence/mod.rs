@@ -60,6 +60,8 @@ macro_rules! background {
 EscapeSequenceBool!(alternate_screen, "\x1b[?1049h", "\x1b[?1049l");
 EscapeSequenceBool!(hide_cursor, "\x1b[?25l", "\x1b[?25h");
 EscapeSequenceBool!(color_reverse, "\x1b[7m", "\x1b[27m");
+// ボタンを押したときと離したときをSGR形式で報告させる
+EscapeSequenceBool!(mouse_tracking, "\x1b[?1000h\x1b[?1006h", "\x1b[?1006l\x1b[?1000l");
 EscapeSequence!(home_cursor, "\x1b[H");
 EscapeSequence!(clear, "\x1b[2J");
 
@@ -0,0 +1,358 @@
+#![allow(dead_code)]
+
+// SGRで指定された文字の見た目
+// 色はSGRのパラメータのまま持つ。例: "91", "38;5;208"
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Style {
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    // "\x1b[...m"の...の部分を順に反映する
+    pub fn apply_sgr(&mut self, params: &str) {
+        let params: Vec<&str> = params.split(';').collect();
+        let mut i = 0;
+        while i < params.len() {
+            let param = params[i];
+            match param.parse::<u8>().unwrap_or(0) {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.reverse = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                },
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.reverse = false,
+                n @ 30..=37 | n @ 90..=97 => self.foreground = Some(n.to_string()),
+                39 => self.foreground = None,
+                n @ 40..=47 | n @ 100..=107 => self.background = Some(n.to_string()),
+                49 => self.background = None,
+                n @ 38 | n @ 48 => {
+                    // 38;5;n か 38;2;r;g;b
+                    let len = match params.get(i + 1) {
+                        Some(&"5") => 2,
+                        Some(&"2") => 4,
+                        _ => 0
+                    };
+                    let end = (i + 1 + len).min(params.len());
+                    let color = Some(params[i..end].join(";"));
+                    if n == 38 {
+                        self.foreground = color;
+                    } else {
+                        self.background = color;
+                    }
+                    i = end - 1;
+                },
+                _ => ()
+            }
+            i += 1;
+        }
+    }
+
+    // 何も指定されていない状態からこの見た目にするSGR
+    pub fn to_sgr(&self) -> String {
+        let mut params = vec!["0".to_string()];
+        if self.bold {
+            params.push("1".to_string());
+        }
+        if self.dim {
+            params.push("2".to_string());
+        }
+        if self.italic {
+            params.push("3".to_string());
+        }
+        if self.underline {
+            params.push("4".to_string());
+        }
+        if self.reverse {
+            params.push("7".to_string());
+        }
+        if let Some(foreground) = &self.foreground {
+            params.push(foreground.clone());
+        }
+        if let Some(background) = &self.background {
+            params.push(background.clone());
+        }
+        format!("\x1b[{}m", params.join(";"))
+    }
+}
+
+// 端末の１マス
+// 全角の文字は２マスを使い、右のマスはwidthが0になる
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Cell {
+    pub ch: char,
+    pub style: Style,
+    pub width: usize,
+}
+
+impl Default for Cell {
+    fn default() -> Cell {
+        Cell {
+            ch: ' ',
+            style: Style::default(),
+            width: 1,
+        }
+    }
+}
+
+// 文字が端末で使う列数
+pub fn char_width(c: char) -> usize {
+    let code = c as u32;
+    match code {
+        0 => 0,
+        0x01..=0x1f | 0x7f..=0x9f => 0,
+        // 結合文字
+        0x0300..=0x036f | 0x200b..=0x200f | 0xfe00..=0xfe0f => 0,
+        0x1100..=0x115f | 0x2e80..=0x303e | 0x3041..=0x33ff | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff | 0xa000..=0xa4cf | 0xac00..=0xd7a3 | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f | 0xff00..=0xff60 | 0xffe0..=0xffe6
+        // 絵文字
+        | 0x1f300..=0x1f64f | 0x1f680..=0x1f6ff | 0x1f7e0..=0x1f7eb | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1
+    }
+}
+
+// 文字列が端末で使う列数。色のエスケープシーケンスは数えない
+pub fn text_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut is_escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => is_escape = true,
+            'm' if is_escape => is_escape = false,
+            c if !is_escape => width += char_width(c),
+            _ => ()
+        }
+    }
+    width
+}
+
+// 画面全体のマス
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+    height: usize,
+    width: usize,
+    cells: Vec<Vec<Cell>>,
+    // 書き終えたときのカーソルの(行, 列)
+    cursor: (usize, usize),
+}
+
+impl Frame {
+    pub fn new(height: usize, width: usize) -> Frame {
+        Frame {
+            height,
+            width,
+            cells: vec![vec![Cell::default(); width]; height],
+            cursor: (0, 0),
+        }
+    }
+
+    pub fn get_size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    pub fn get_cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        self.cells.get(row)?.get(col)
+    }
+
+    // 見た目を除いた各行の文字。行末の空白は除く
+    pub fn to_lines(&self) -> Vec<String> {
+        self.cells.iter()
+            .map(|line| {
+                let line: String = line.iter()
+                    .filter(|cell| cell.width != 0)
+                    .map(|cell| cell.ch)
+                    .collect();
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    // 空の画面の左上からtextを書いたときの画面
+    // 行の端では端末と同じように折り返し、画面の下からはみ出した分は捨てる
+    pub fn from_text(text: &str, height: usize, width: usize) -> Frame {
+        let mut frame = Frame::new(height, width);
+        let mut style = Style::default();
+        let (mut row, mut col) = (0, 0);
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => {
+                    if chars.peek() != Some(&'[') {
+                        continue;
+                    }
+                    chars.next();
+                    let mut params = String::new();
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            if c == 'm' {
+                                style.apply_sgr(&params);
+                            }
+                            break;
+                        }
+                        params.push(c);
+                    }
+                },
+                '\n' => {
+                    row += 1;
+                    col = 0;
+                },
+                '\r' => col = 0,
+                c => {
+                    let char_width = char_width(c);
+                    if char_width == 0 {
+                        continue;
+                    }
+                    if width < col + char_width {
+                        row += 1;
+                        col = 0;
+                    }
+                    if height <= row || width < char_width {
+                        continue;
+                    }
+                    frame.cells[row][col] = Cell { ch: c, style: style.clone(), width: char_width };
+                    for i in 1..char_width {
+                        frame.cells[row][col + i] = Cell { ch: ' ', style: style.clone(), width: 0 };
+                    }
+                    col += char_width;
+                }
+            }
+        }
+        frame.cursor = (row.min(height.saturating_sub(1)), col.min(width.saturating_sub(1)));
+
+        frame
+    }
+
+    // previousが表示されている画面をこの画面にするための出力
+    // 変わったマスだけを書き、最後にカーソルを書き終えた位置に置く
+    pub fn diff(&self, previous: &Frame) -> String {
+        let mut output = String::new();
+        // 端末のカーソルの位置と見た目。分からなければNone
+        let mut position = None;
+        let mut style = None;
+
+        for (row, line) in self.cells.iter().enumerate() {
+            for (col, cell) in line.iter().enumerate() {
+                // 全角の右半分は左半分と一緒に書く
+                if cell.width == 0 || previous.get_cell(row, col) == Some(cell) {
+                    continue;
+                }
+
+                if position != Some((row, col)) {
+                    output += &move_cursor!(row + 1, col + 1);
+                }
+                if style.as_ref() != Some(&cell.style) {
+                    output += &cell.style.to_sgr();
+                    style = Some(cell.style.clone());
+                }
+                output.push(cell.ch);
+                position = Some((row, col + cell.width));
+            }
+        }
+
+        if style.is_some() {
+            output += "\x1b[0m";
+        }
+        if position != Some(self.cursor) {
+            output += &move_cursor!(self.cursor.0 + 1, self.cursor.1 + 1);
+        }
+        output
+    }
+}
+
+#[test]
+fn test_style_apply_sgr() {
+    let mut style = Style::default();
+    style.apply_sgr("91");
+    assert_eq!(style.foreground, Some("91".to_string()));
+    style.apply_sgr("7");
+    style.apply_sgr("1;38;5;208;48;2;1;2;3");
+    assert!(style.reverse && style.bold);
+    assert_eq!(style.foreground, Some("38;5;208".to_string()));
+    assert_eq!(style.background, Some("48;2;1;2;3".to_string()));
+    assert_eq!(style.to_sgr(), "\x1b[0;1;7;38;5;208;48;2;1;2;3m");
+    style.apply_sgr("27;39");
+    assert!(!style.reverse);
+    assert_eq!(style.foreground, None);
+    style.apply_sgr("");
+    assert_eq!(style, Style::default());
+    assert_eq!(style.to_sgr(), "\x1b[0m");
+}
+#[test]
+fn test_char_width() {
+    assert_eq!(char_width('a'), 1);
+    assert_eq!(char_width('１'), 2);
+    assert_eq!(char_width('あ'), 2);
+    assert_eq!(char_width('🎉'), 2);
+    assert_eq!(char_width('─'), 1);
+    assert_eq!(char_width('🚩'), 2);
+    assert_eq!(char_width('🟦'), 2);
+    assert_eq!(char_width('┌'), 1);
+    assert_eq!(char_width('\u{0301}'), 0);
+}
+#[test]
+fn test_text_width() {
+    assert_eq!(text_width("ab"), 2);
+    assert_eq!(text_width("[]\x1b[93m/>\x1b[0m１"), 6);
+    assert_eq!(text_width("\x1b[7m🟦\x1b[27m# "), 4);
+}
+#[test]
+fn test_frame_from_text() {
+    let frame = Frame::from_text("a\x1b[91m１\x1b[0mb\ncdefg", 3, 4);
+    assert_eq!(frame.get_cell(0, 0).unwrap().ch, 'a');
+    let cell = frame.get_cell(0, 1).unwrap();
+    assert_eq!((cell.ch, cell.width), ('１', 2));
+    assert_eq!(cell.style.foreground, Some("91".to_string()));
+    assert_eq!(frame.get_cell(0, 2).unwrap().width, 0);
+    assert_eq!(frame.get_cell(0, 3).unwrap(), &Cell { ch: 'b', ..Cell::default() });
+    // 折り返す
+    assert_eq!(frame.get_cell(1, 3).unwrap().ch, 'f');
+    assert_eq!(frame.get_cell(2, 0).unwrap().ch, 'g');
+    assert_eq!(frame.cursor, (2, 1));
+
+    // 全角の文字が行の最後に入らなければ次の行に書く
+    let frame = Frame::from_text("abc１", 2, 4);
+    assert_eq!(frame.get_cell(0, 3).unwrap().ch, ' ');
+    assert_eq!(frame.get_cell(1, 0).unwrap().ch, '１');
+
+    // はみ出した分は捨てる
+    let frame = Frame::from_text("a\nb\nc", 2, 4);
+    assert_eq!(frame.get_cell(1, 0).unwrap().ch, 'b');
+    assert_eq!(frame.get_size(), (2, 4));
+    assert_eq!(frame.to_lines(), vec!["a", "b"]);
+    assert_eq!(Frame::from_text(" \x1b[7m１\x1b[0m2 ", 1, 6).to_lines(), vec![" １2"]);
+}
+#[test]
+fn test_frame_diff() {
+    let blank = Frame::new(2, 6);
+    let frame = Frame::from_text("ab\n  \x1b[7mcd", 2, 6);
+    assert_eq!(frame.diff(&blank),
+               "\x1b[1;1H\x1b[0mab\x1b[2;3H\x1b[0;7mcd\x1b[0m");
+    assert_eq!(frame.diff(&frame), "\x1b[2;5H");
+
+    // 変わったマスだけ書く
+    let next = Frame::from_text("ab\n  \x1b[7mce", 2, 6);
+    assert_eq!(next.diff(&frame), "\x1b[2;4H\x1b[0;7me\x1b[0m");
+    let next = Frame::from_text("xb\n  \x1b[7mcd", 2, 6);
+    assert_eq!(next.diff(&frame), "\x1b[1;1H\x1b[0mx\x1b[0m\x1b[2;5H");
+
+    // 全角の文字を書き換える
+    let wide = Frame::from_text("１２", 1, 6);
+    let next = Frame::from_text("１a", 1, 6);
+    assert_eq!(next.diff(&wide), "\x1b[1;3H\x1b[0ma \x1b[0m\x1b[1;4H");
+    assert_eq!(wide.diff(&next), "\x1b[1;3H\x1b[0m２\x1b[0m");
+}
@@ -0,0 +1,576 @@
+#![allow(dead_code)]
+
+use std::fmt;
+use std::str::FromStr;
+
+// 修飾キー
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { shift: false, alt: false, ctrl: false };
+
+    // CSIの修飾キーのパラメータ。1 + (shift: 1, alt: 2, ctrl: 4)
+    fn from_param(param: u32) -> Modifiers {
+        let bits = param.saturating_sub(1);
+        Modifiers {
+            shift: bits & 1 != 0,
+            alt: bits & 2 != 0,
+            ctrl: bits & 4 != 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum KeyCode {
+    Char(char),
+    Enter,
+    Tab,
+    BackTab,
+    Backspace,
+    Esc,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    F(u8),
+}
+
+// 押されたキー
+// Shiftを押した文字は大文字などの文字そのものになり、shiftはfalseのまま
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: Modifiers,
+}
+
+impl Key {
+    pub fn new(code: KeyCode) -> Key {
+        Key { code, modifiers: Modifiers::NONE }
+    }
+
+    pub fn with_modifiers(code: KeyCode, modifiers: Modifiers) -> Key {
+        Key { code, modifiers }
+    }
+
+    pub fn char(c: char) -> Key {
+        Key::new(KeyCode::Char(c))
+    }
+
+    pub fn ctrl(c: char) -> Key {
+        Key::with_modifiers(KeyCode::Char(c), Modifiers { ctrl: true, ..Modifiers::NONE })
+    }
+}
+
+// 設定ファイルやヘルプで使うキーの名前
+// 例: "n", "N", "space", "enter", "up", "f5", "ctrl-r", "alt-shift-left"
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.ctrl {
+            write!(f, "ctrl-")?;
+        }
+        if self.modifiers.alt {
+            write!(f, "alt-")?;
+        }
+        if self.modifiers.shift {
+            write!(f, "shift-")?;
+        }
+
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            code => {
+                let name = KEY_NAMES.iter()
+                    .find(|(_, named)| *named == code)
+                    .map(|(name, _)| *name)
+                    .unwrap_or("?");
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+const KEY_NAMES: [(&str, KeyCode); 16] = [
+    ("space", KeyCode::Char(' ')),
+    ("enter", KeyCode::Enter),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("backspace", KeyCode::Backspace),
+    ("esc", KeyCode::Esc),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("insert", KeyCode::Insert),
+    ("delete", KeyCode::Delete),
+];
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Key, String> {
+        let mut modifiers = Modifiers::NONE;
+        let mut rest = s;
+        loop {
+            // "-"そのものもキーの名前になる
+            let (prefix, flag) = if rest.starts_with("ctrl-") && rest.len() > 5 {
+                ("ctrl-", &mut modifiers.ctrl)
+            } else if rest.starts_with("alt-") && rest.len() > 4 {
+                ("alt-", &mut modifiers.alt)
+            } else if rest.starts_with("shift-") && rest.len() > 6 {
+                ("shift-", &mut modifiers.shift)
+            } else {
+                break;
+            };
+            *flag = true;
+            rest = &rest[prefix.len()..];
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => {
+                // Shiftを押した文字は大文字として届く
+                if modifiers.shift && !c.is_uppercase() && c.to_uppercase().count() == 1 {
+                    modifiers.shift = false;
+                    KeyCode::Char(c.to_uppercase().next().unwrap())
+                } else {
+                    KeyCode::Char(c)
+                }
+            },
+            _ => {
+                let name = rest.to_lowercase();
+                match KEY_NAMES.iter().find(|(named, _)| *named == name) {
+                    Some((_, code)) => *code,
+                    None => match name.strip_prefix('f').map(u8::from_str) {
+                        Some(Ok(n)) if (1..=12).contains(&n) => KeyCode::F(n),
+                        _ => return Err(format!("unknown key '{}'", s))
+                    }
+                }
+            }
+        };
+
+        Ok(Key::with_modifiers(code, modifiers))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum MouseKind {
+    Press,
+    Release,
+    Drag,
+}
+
+// SGR形式で報告されたマウスの操作
+// colとrowは端末の左上を(0, 0)とした位置
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Mouse {
+    pub kind: MouseKind,
+    pub button: MouseButton,
+    pub col: usize,
+    pub row: usize,
+    pub modifiers: Modifiers,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Event {
+    Key(Key),
+    Mouse(Mouse),
+}
+
+// バイト列を解釈した結果
+enum Decoded {
+    Event(Event, usize),
+    // 解釈できない列。読み飛ばすバイト数
+    Skip(usize),
+    // 続きのバイトが必要
+    Incomplete,
+}
+
+// 端末から読んだバイト列をキーに変換する
+// エスケープシーケンスとUTF-8の文字は、途中までしか読めていなければ続きを待つ
+#[derive(Debug, Default)]
+pub struct KeyDecoder {
+    buf: Vec<u8>,
+}
+
+impl KeyDecoder {
+    pub fn new() -> KeyDecoder {
+        KeyDecoder { buf: Vec::new() }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    // 途中までのバイト列が残っているならtrue
+    pub fn is_pending(&self) -> bool {
+        !self.buf.is_empty()
+    }
+
+    pub fn next_event(&mut self) -> Option<Event> {
+        loop {
+            match decode(&self.buf) {
+                Decoded::Event(event, len) => {
+                    self.buf.drain(..len);
+                    return Some(event);
+                },
+                Decoded::Skip(len) => {
+                    self.buf.drain(..len);
+                },
+                Decoded::Incomplete => return None
+            }
+        }
+    }
+
+    // 続きが来ないときに、残っているバイト列をキーにする
+    // 単独のEscはここでEscになる
+    pub fn flush(&mut self) -> Option<Event> {
+        if let Some(event) = self.next_event() {
+            return Some(event);
+        }
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        let byte = self.buf.remove(0);
+        if byte == 0x1b {
+            return Some(Event::Key(Key::new(KeyCode::Esc)));
+        }
+        // 途中で切れたUTF-8
+        Some(Event::Key(Key::char(std::char::REPLACEMENT_CHARACTER)))
+    }
+}
+
+fn key_of(key: Key, len: usize) -> Decoded {
+    Decoded::Event(Event::Key(key), len)
+}
+
+fn decode(buf: &[u8]) -> Decoded {
+    let byte = match buf.first() {
+        Some(&byte) => byte,
+        None => return Decoded::Incomplete
+    };
+
+    match byte {
+        0x1b => decode_escape(buf),
+        b'\r' | b'\n' => key_of(Key::new(KeyCode::Enter), 1),
+        b'\t' => key_of(Key::new(KeyCode::Tab), 1),
+        0x7f | 0x08 => key_of(Key::new(KeyCode::Backspace), 1),
+        0x00 => key_of(Key::ctrl(' '), 1),
+        0x01..=0x1a => key_of(Key::ctrl((b'a' + byte - 1) as char), 1),
+        0x1c..=0x1f => key_of(Key::ctrl((b'4' + byte - 0x1c) as char), 1),
+        0x20..=0x7e => key_of(Key::char(byte as char), 1),
+        _ => decode_utf8(buf)
+    }
+}
+
+fn decode_utf8(buf: &[u8]) -> Decoded {
+    let len = match buf[0] {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return key_of(Key::char(std::char::REPLACEMENT_CHARACTER), 1)
+    };
+    if buf.len() < len {
+        // 続きのバイトでないものが来ていれば壊れている
+        if buf[1..].iter().any(|b| b & 0xc0 != 0x80) {
+            return key_of(Key::char(std::char::REPLACEMENT_CHARACTER), 1);
+        }
+        return Decoded::Incomplete;
+    }
+
+    match std::str::from_utf8(&buf[..len]) {
+        Ok(s) => key_of(Key::char(s.chars().next().unwrap()), len),
+        Err(_) => key_of(Key::char(std::char::REPLACEMENT_CHARACTER), 1)
+    }
+}
+
+fn decode_escape(buf: &[u8]) -> Decoded {
+    match buf.get(1) {
+        None => Decoded::Incomplete,
+        Some(b'[') => decode_csi(buf),
+        Some(b'O') => decode_ss3(buf),
+        Some(0x1b) => key_of(Key::new(KeyCode::Esc), 1),
+        // Esc + キーはAlt + キー
+        Some(_) => match decode(&buf[1..]) {
+            Decoded::Event(Event::Key(mut key), len) => {
+                key.modifiers.alt = true;
+                key_of(key, len + 1)
+            },
+            Decoded::Event(Event::Mouse(_), _) => key_of(Key::new(KeyCode::Esc), 1),
+            Decoded::Skip(_) => key_of(Key::new(KeyCode::Esc), 1),
+            Decoded::Incomplete => Decoded::Incomplete
+        }
+    }
+}
+
+// ESC O <final>
+fn decode_ss3(buf: &[u8]) -> Decoded {
+    let code = match buf.get(2) {
+        None => return Decoded::Incomplete,
+        Some(&byte) => match byte {
+            b'A' => KeyCode::Up,
+            b'B' => KeyCode::Down,
+            b'C' => KeyCode::Right,
+            b'D' => KeyCode::Left,
+            b'H' => KeyCode::Home,
+            b'F' => KeyCode::End,
+            b'P'..=b'S' => KeyCode::F(byte - b'P' + 1),
+            _ => return Decoded::Skip(3)
+        }
+    };
+
+    key_of(Key::new(code), 3)
+}
+
+// ESC [ <パラメータ> <中間> <final>
+fn decode_csi(buf: &[u8]) -> Decoded {
+    let mut end = 2;
+    loop {
+        match buf.get(end) {
+            None => return Decoded::Incomplete,
+            Some(0x20..=0x3f) => end += 1,
+            Some(0x40..=0x7e) => break,
+            // 途中で壊れている
+            Some(_) => return Decoded::Skip(end)
+        }
+    }
+    let len = end + 1;
+    let final_byte = buf[end];
+    if buf[2] == b'<' {
+        return decode_sgr_mouse(&buf[3..end], final_byte, len);
+    }
+    let params: Vec<u32> = std::str::from_utf8(&buf[2..end]).unwrap_or("")
+        .split(';')
+        .map(|param| param.parse().unwrap_or(0))
+        .collect();
+    let param = |i: usize| params.get(i).cloned().unwrap_or(0);
+    let modifiers = Modifiers::from_param(param(1));
+
+    let code = match final_byte {
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'Z' => KeyCode::BackTab,
+        b'P'..=b'S' => KeyCode::F(final_byte - b'P' + 1),
+        b'~' => match param(0) {
+            1 | 7 => KeyCode::Home,
+            2 => KeyCode::Insert,
+            3 => KeyCode::Delete,
+            4 | 8 => KeyCode::End,
+            5 => KeyCode::PageUp,
+            6 => KeyCode::PageDown,
+            n @ 11..=15 => KeyCode::F((n - 10) as u8),
+            n @ 17..=21 => KeyCode::F((n - 11) as u8),
+            n @ 23..=24 => KeyCode::F((n - 12) as u8),
+            _ => return Decoded::Skip(len)
+        },
+        _ => return Decoded::Skip(len)
+    };
+
+    key_of(Key::with_modifiers(code, modifiers), len)
+}
+
+// ESC [ < <ボタン> ; <列> ; <行> <M | m>
+// 列と行は1始まり。Mは押したとき、mは離したとき
+fn decode_sgr_mouse(params: &[u8], final_byte: u8, len: usize) -> Decoded {
+    let params: Vec<usize> = match std::str::from_utf8(params).unwrap_or("")
+        .split(';')
+        .map(|param| param.parse())
+        .collect::<Result<_, _>>() {
+        Ok(params) => params,
+        Err(_) => return Decoded::Skip(len)
+    };
+    if params.len() != 3 || params[1] == 0 || params[2] == 0 {
+        return Decoded::Skip(len);
+    }
+
+    let code = params[0];
+    let button = match (code & 64 != 0, code & 3) {
+        (false, 0) => MouseButton::Left,
+        (false, 1) => MouseButton::Middle,
+        (false, 2) => MouseButton::Right,
+        (true, 0) => MouseButton::WheelUp,
+        (true, 1) => MouseButton::WheelDown,
+        _ => return Decoded::Skip(len)
+    };
+    let kind = match final_byte {
+        b'm' => MouseKind::Release,
+        b'M' if code & 32 != 0 => MouseKind::Drag,
+        b'M' => MouseKind::Press,
+        _ => return Decoded::Skip(len)
+    };
+    let modifiers = Modifiers {
+        shift: code & 4 != 0,
+        alt: code & 8 != 0,
+        ctrl: code & 16 != 0,
+    };
+
+    Decoded::Event(Event::Mouse(Mouse {
+        kind,
+        button,
+        col: params[1] - 1,
+        row: params[2] - 1,
+        modifiers,
+    }), len)
+}
+
+#[cfg(test)]
+fn decode_all(bytes: &[u8]) -> Vec<Key> {
+    let mut decoder = KeyDecoder::new();
+    decoder.feed(bytes);
+    let mut keys = Vec::new();
+    while let Some(event) = decoder.next_event() {
+        if let Event::Key(key) = event {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
+#[test]
+fn test_key_decoder_ascii() {
+    assert_eq!(decode_all(b"nE"), vec![Key::char('n'), Key::char('E')]);
+    assert_eq!(decode_all(b"\n\r\t\x7f"),
+               vec![Key::new(KeyCode::Enter), Key::new(KeyCode::Enter),
+                    Key::new(KeyCode::Tab), Key::new(KeyCode::Backspace)]);
+    assert_eq!(decode_all(b"\x01\x0c"), vec![Key::ctrl('a'), Key::ctrl('l')]);
+}
+#[test]
+fn test_key_decoder_csi() {
+    assert_eq!(decode_all(b"\x1b[A\x1b[B\x1b[C\x1b[D"),
+               vec![Key::new(KeyCode::Up), Key::new(KeyCode::Down),
+                    Key::new(KeyCode::Right), Key::new(KeyCode::Left)]);
+    assert_eq!(decode_all(b"\x1b[H\x1b[F\x1b[1~\x1b[4~"),
+               vec![Key::new(KeyCode::Home), Key::new(KeyCode::End),
+                    Key::new(KeyCode::Home), Key::new(KeyCode::End)]);
+    assert_eq!(decode_all(b"\x1b[5~\x1b[6~\x1b[2~\x1b[3~"),
+               vec![Key::new(KeyCode::PageUp), Key::new(KeyCode::PageDown),
+                    Key::new(KeyCode::Insert), Key::new(KeyCode::Delete)]);
+    assert_eq!(decode_all(b"\x1b[15~\x1b[17~\x1b[24~\x1b[1;2P"),
+               vec![Key::new(KeyCode::F(5)), Key::new(KeyCode::F(6)),
+                    Key::new(KeyCode::F(12)),
+                    Key::with_modifiers(KeyCode::F(1),
+                                        Modifiers { shift: true, ..Modifiers::NONE })]);
+    assert_eq!(decode_all(b"\x1b[1;5C\x1b[1;3A\x1b[6;2~"),
+               vec![Key::with_modifiers(KeyCode::Right,
+                                        Modifiers { ctrl: true, ..Modifiers::NONE }),
+                    Key::with_modifiers(KeyCode::Up,
+                                        Modifiers { alt: true, ..Modifiers::NONE }),
+                    Key::with_modifiers(KeyCode::PageDown,
+                                        Modifiers { shift: true, ..Modifiers::NONE })]);
+    // 知らないシーケンスは読み飛ばす
+    assert_eq!(decode_all(b"\x1b[99~a\x1b[?1;2cb"), vec![Key::char('a'), Key::char('b')]);
+}
+#[test]
+fn test_key_decoder_ss3() {
+    assert_eq!(decode_all(b"\x1bOA\x1bOH\x1bOP\x1bOS"),
+               vec![Key::new(KeyCode::Up), Key::new(KeyCode::Home),
+                    Key::new(KeyCode::F(1)), Key::new(KeyCode::F(4))]);
+}
+#[test]
+fn test_key_decoder_utf8() {
+    assert_eq!(decode_all("あé🎉".as_bytes()),
+               vec![Key::char('あ'), Key::char('é'), Key::char('🎉')]);
+    assert_eq!(decode_all(b"\xff\xe3a"),
+               vec![Key::char(std::char::REPLACEMENT_CHARACTER),
+                    Key::char(std::char::REPLACEMENT_CHARACTER), Key::char('a')]);
+}
+#[test]
+fn test_key_decoder_split() {
+    // 途中までしか来ていなければ続きを待つ
+    let mut decoder = KeyDecoder::new();
+    decoder.feed(b"\x1b[1;");
+    assert_eq!(decoder.next_event(), None);
+    assert!(decoder.is_pending());
+    decoder.feed(b"5D");
+    assert_eq!(decoder.next_event(),
+               Some(Event::Key(Key::with_modifiers(KeyCode::Left,
+                                                   Modifiers { ctrl: true, ..Modifiers::NONE }))));
+    assert!(!decoder.is_pending());
+
+    let bytes = "あ".as_bytes();
+    decoder.feed(&bytes[..2]);
+    assert_eq!(decoder.next_event(), None);
+    decoder.feed(&bytes[2..]);
+    assert_eq!(decoder.next_event(), Some(Event::Key(Key::char('あ'))));
+}
+#[test]
+fn test_key_decoder_esc() {
+    // 単独のEscは続きが来なかったときに決まる
+    let mut decoder = KeyDecoder::new();
+    decoder.feed(b"\x1b");
+    assert_eq!(decoder.next_event(), None);
+    assert_eq!(decoder.flush(), Some(Event::Key(Key::new(KeyCode::Esc))));
+    assert_eq!(decoder.flush(), None);
+
+    let mut alt_x = Key::char('x');
+    alt_x.modifiers.alt = true;
+    assert_eq!(decode_all(b"\x1bx\x1b\x1b[A"),
+               vec![alt_x, Key::new(KeyCode::Esc), Key::new(KeyCode::Up)]);
+}
+#[test]
+fn test_key_name() {
+    for name in &["n", "N", "-", "?", "space", "enter", "up", "pagedown", "f5", "f12",
+                  "ctrl-r", "alt-x", "ctrl-alt-shift-left", "ctrl--"] {
+        assert_eq!(Key::from_str(name).unwrap().to_string(), *name);
+    }
+    assert_eq!(Key::from_str("shift-n"), Ok(Key::char('N')));
+    assert_eq!(Key::from_str("Enter"), Ok(Key::new(KeyCode::Enter)));
+    assert_eq!(Key::from_str(" "), Ok(Key::char(' ')));
+    assert!(Key::from_str("f13").is_err());
+    assert!(Key::from_str("foo").is_err());
+    assert!(Key::from_str("").is_err());
+}
+#[test]
+fn test_key_decoder_mouse() {
+    let mut decoder = KeyDecoder::new();
+    decoder.feed(b"\x1b[<0;11;3M\x1b[<2;1;1m\x1b[<1;5;4M\x1b[<32;6;4M\x1b[<65;1;1M\x1b[<16;2;2M");
+    let mut events = Vec::new();
+    while let Some(Event::Mouse(mouse)) = decoder.next_event() {
+        events.push((mouse.kind, mouse.button, mouse.col, mouse.row));
+    }
+    assert_eq!(events, vec![(MouseKind::Press, MouseButton::Left, 10, 2),
+                            (MouseKind::Release, MouseButton::Right, 0, 0),
+                            (MouseKind::Press, MouseButton::Middle, 4, 3),
+                            (MouseKind::Drag, MouseButton::Left, 5, 3),
+                            (MouseKind::Press, MouseButton::WheelDown, 0, 0),
+                            (MouseKind::Press, MouseButton::Left, 1, 1)]);
+
+    // 途中までなら続きを待ち、壊れていれば読み飛ばす
+    decoder.feed(b"\x1b[<0;1");
+    assert_eq!(decoder.next_event(), None);
+    decoder.feed(b"0;2M\x1b[<0;0;0Ma");
+    match decoder.next_event() {
+        Some(Event::Mouse(mouse)) => assert_eq!((mouse.col, mouse.row), (9, 1)),
+        event => panic!("{:?}", event)
+    }
+    assert_eq!(decoder.next_event(), Some(Event::Key(Key::char('a'))));
+}
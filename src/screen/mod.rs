@@ -1,231 +1,446 @@
 #![allow(dead_code)]
 
-use std::io::{self, Read};
+use std::io;
+use std::time::{Duration, Instant};
 
 extern crate libc;
 
-mod termios;
-use crate::screen::termios::Termios;
+pub mod termios;
 
 #[macro_use]
 mod escape_sequence;
 
+pub mod key;
+use crate::screen::key::{Event, Key, KeyCode, KeyDecoder};
 
-pub struct Screen {
+pub mod viewport;
+use crate::screen::viewport::ScrollIndicators;
+
+pub mod frame;
+use crate::screen::frame::{text_width, Frame};
+
+pub mod vt;
+
+pub mod backend;
+use crate::screen::backend::Backend;
+use crate::screen::backend::terminal::TerminalBackend;
+use crate::screen::termios::TermiosError;
+use crate::theme::GLYPH_WIDTH;
+
+
+pub struct Screen<B: Backend = TerminalBackend> {
     is_debug: bool,
-    termios: Termios,
+    backend: B,
     terminal_width: usize,
+    terminal_height: usize,
     board_buffer: String,
     top_bar_buffer: String,
+    bottom_bar_buffer: String,
+    decoder: KeyDecoder,
+    board_layout: BoardLayout,
+    // 盤面の上と左に付ける列の名前と行の番号の(列数, 行数)
+    ruler_size: (usize, usize),
+    scroll_indicators: ScrollIndicators,
+    // 最後に表示した画面。Noneなら次は全体を描き直す
+    last_frame: Option<Frame>,
 //    score_buffer: String,
 }
 
-impl Screen {
-    pub fn new() -> Screen {
-        let mut termios = Termios::new();
+// マス１つの表示上の幅
+const SQUARE_WIDTH: usize = GLYPH_WIDTH;
+// 盤面の左右にスクロールの印を表示するための幅
+const INDICATOR_WIDTH: usize = 2;
 
-        termios.mode_off(libc::ICANON | libc::ECHO);
-        print!(alternate_screen!(enable));
+// 端末のどこに盤面を表示したか
+#[derive(Debug, Default, PartialEq)]
+struct BoardLayout {
+    // 盤面の一番上の行
+    top: usize,
+    // 盤面の左端の列
+    left: usize,
+    // 表示上の幅と行数。列の名前と行の番号を含む
+    width: usize,
+    height: usize,
+    // 列の名前と行の番号の(列数, 行数)
+    ruler_size: (usize, usize),
+}
 
-        Screen {
-            is_debug: false,
-            termios,
-            terminal_width: 0,
-            board_buffer: String::new(),
-            top_bar_buffer: String::new(),
+impl BoardLayout {
+    fn new(board_buffer: &str, terminal_width: usize, top: usize,
+           ruler_size: (usize, usize)) -> BoardLayout {
+        // 全角の文字や絵文字は２列になる
+        let width = board_buffer.lines().map(text_width).max().unwrap_or(0);
+
+        BoardLayout {
+            top,
+            // {:^}は余白が奇数なら右に１つ多く詰める
+            left: terminal_width.saturating_sub(width) / 2,
+            width,
+            height: board_buffer.lines().count(),
+            ruler_size,
         }
     }
 
-    pub fn new_with_terminal_width(width: usize) -> Screen {
-        let mut termios = Termios::new();
+    // 端末の位置(0始まりの列, 行)にあるマスの(x, y)
+    fn square_at(&self, col: usize, row: usize) -> Option<(usize, usize)> {
+        let (left, top) = (self.left + self.ruler_size.0, self.top + self.ruler_size.1);
+        if col < left || row < top {
+            return None;
+        }
+        let (col, row) = (col - left, row - top);
+        if self.width <= col + self.ruler_size.0 || self.height <= row + self.ruler_size.1 {
+            return None;
+        }
 
-        termios.mode_off(libc::ICANON | libc::ECHO);
-        print!(alternate_screen!(enable));
+        Some((col / SQUARE_WIDTH, row))
+    }
+}
+
+// エスケープシーケンスの続きを待つ時間。これを過ぎたら単独のEscとみなす
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(25);
+
+impl Screen<TerminalBackend> {
+    pub fn new() -> Result<Screen, TermiosError> {
+        let mut screen = Screen::with_backend(TerminalBackend::new()?);
+        // 大きさを指定しなければ中央に寄せない
+        screen.set_terminal_size(0, 0);
+        Ok(screen)
+    }
+
+    pub fn new_with_terminal_width(width: usize) -> Result<Screen, TermiosError> {
+        Screen::new_with_terminal_size(0, width)
+    }
+
+    pub fn new_with_terminal_size(height: usize, width: usize) -> Result<Screen, TermiosError> {
+        let mut screen = Screen::new()?;
+        screen.set_terminal_size(height, width);
+        Ok(screen)
+    }
+
+    pub fn new_debug_mode() -> Result<Screen, TermiosError> {
+        let mut screen = Screen::with_backend(TerminalBackend::new_debug_mode()?);
+        screen.is_debug = true;
+        Ok(screen)
+    }
+}
+
+impl<B: Backend> Screen<B> {
+    // 大きさはバックエンドから調べる
+    pub fn with_backend(backend: B) -> Screen<B> {
+        let (terminal_height, terminal_width) = backend.get_size();
 
         Screen {
             is_debug: false,
-            termios,
-            terminal_width: width,
+            backend,
+            terminal_width,
+            terminal_height,
             board_buffer: String::new(),
             top_bar_buffer: String::new(),
+            bottom_bar_buffer: String::new(),
+            decoder: KeyDecoder::new(),
+            board_layout: BoardLayout::default(),
+            ruler_size: (0, 0),
+            scroll_indicators: ScrollIndicators::default(),
+            last_frame: None,
         }
     }
 
-    pub fn new_debug_mode() -> Screen {
-        let mut termios = Termios::new();
+    pub fn get_backend(&self) -> &B {
+        &self.backend
+    }
 
-        termios.mode_off(libc::ICANON | libc::ECHO);
-        // print!(alternate_screen!(enable));
+    pub fn into_backend(self) -> B {
+        self.backend
+    }
 
-        Screen {
-            is_debug: true,
-            termios,
-            terminal_width: 0,
-            board_buffer: String::new(),
-            top_bar_buffer: String::new(),
+    // 端末の大きさが変わったときに呼ぶ
+    // 中央に寄せ直すには、盤面とバーをもう一度setする
+    pub fn set_terminal_size(&mut self, height: usize, width: usize) {
+        self.terminal_height = height;
+        self.terminal_width = width;
+    }
+
+    pub fn get_terminal_size(&self) -> (usize, usize) {
+        (self.terminal_height, self.terminal_width)
+    }
+
+    // 端末の大きさが変わるか、止まったあと再開していれば、新しい大きさにしてtrue
+    // 次のprintで画面全体を描き直す
+    pub fn poll_resize(&mut self) -> bool {
+        match self.backend.poll_resize() {
+            Some((height, width)) => {
+                self.set_terminal_size(height, width);
+                self.redraw();
+                true
+            },
+            None => false
+        }
+    }
+
+    // キーが押されるまで待つ。マウスの操作は読み捨てる
+    pub fn read_key(&mut self) -> io::Result<Key> {
+        loop {
+            if let Some(Event::Key(key)) = self.poll_event(Duration::from_secs(60))? {
+                return Ok(key);
+            }
+        }
+    }
+
+    // timeoutの間にキーかマウスの操作がなければOk(None)
+    // 入力が閉じられていればErr
+    pub fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.decoder.next_event() {
+                return Ok(Some(event));
+            }
+
+            // 途中まで読んだシーケンスがあれば、続きは少しだけ待つ
+            let wait = if self.decoder.is_pending() {
+                ESCAPE_TIMEOUT
+            } else {
+                deadline.saturating_duration_since(Instant::now())
+            };
+            match self.backend.read(wait)? {
+                Some(bytes) => self.decoder.feed(&bytes),
+                None => {
+                    if self.decoder.is_pending() {
+                        return Ok(self.decoder.flush());
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    // 端末の位置(0始まりの列, 行)にある盤面のマスの(x, y)
+    // set_boardで中央に寄せた分と、マスの幅を考えて戻す
+    pub fn square_at(&self, col: usize, row: usize) -> Option<(usize, usize)> {
+        self.board_layout.square_at(col, row)
+    }
+
+    // 下のバーにpromptを表示して１行読む。Escで取り消したらOk(None)
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        loop {
+            self.set_bottom_bar(format!("{}{}_", prompt, line));
+            self.print();
+            let key = self.read_key()?;
+            match key.code {
+                KeyCode::Enter => return Ok(Some(line)),
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Backspace => {
+                    line.pop();
+                },
+                KeyCode::Char(c) if !key.modifiers.ctrl && !key.modifiers.alt => line.push(c),
+                _ => ()
+            }
         }
     }
 
-    pub fn read_key(&self) -> char {
-        let mut buf = [1;1];
-        let stdin = io::stdin();
-        let mut stdin_lock = stdin.lock();
-        stdin_lock.read_exact(&mut buf);
+    // 端末の大きさが分かっていれば、前に表示した画面から変わったマスだけを書く
+    pub fn print(&mut self) {
+        let text = self.render();
+        let (height, width) = (self.terminal_height, self.terminal_width);
+        if self.is_debug || height == 0 || width == 0 {
+            self.backend.write(&format!("{}{}{}", clear!(), home_cursor!(), text));
+            return;
+        }
+
+        let frame = Frame::from_text(&text, height, width);
+        let output = match &self.last_frame {
+            Some(last_frame) if last_frame.get_size() == frame.get_size() => frame.diff(last_frame),
+            _ => format!("{}{}{}", clear!(), home_cursor!(), frame.diff(&Frame::new(height, width)))
+        };
+        self.backend.write(&format!("{}{}{}", hide_cursor!(enable), output, hide_cursor!(disable)));
+        self.backend.present(&frame);
+        self.last_frame = Some(frame);
+    }
+
+    // 次のprintで画面全体を描き直す
+    pub fn redraw(&mut self) {
+        self.last_frame = None;
+    }
+
+    fn render(&self) -> String {
+        let mut text = String::new();
+        text += &self.top_bar_buffer;
+        text += "\n";
+        // 盤面の上下の空行には、上下に見えていない部分があるときに印を出す
+        text += &self.indicator_line(self.scroll_indicators.up, "^");
+        text += "\n";
+        text += &self.board_buffer;
+        text += &self.indicator_line(self.scroll_indicators.down, "v");
+        if !self.bottom_bar_buffer.is_empty() {
+            text += "\n";
+            text += &self.bottom_bar_buffer;
+        }
+        text
+    }
 
-        buf[0] as char
+    fn indicator_line(&self, is_shown: bool, mark: &str) -> String {
+        if !is_shown {
+            return String::new();
+        }
+        format!("{:^width$}", mark, width = self.terminal_width).trim_end().to_string()
     }
 
-    pub fn print(&self) {
-        print!("{}{}", clear!(), home_cursor!());
-        println!("{}", self.top_bar_buffer);
-        println!();
-        print!("{}", self.board_buffer);
+    // 盤面を表示できるマスの(列数, 行数)。端末の大きさが分からなければNone
+    // 上のバーを先にsetしておく
+    pub fn get_board_capacity(&self) -> Option<(usize, usize)> {
+        if self.terminal_width == 0 || self.terminal_height == 0 {
+            return None;
+        }
+        let width = self.terminal_width
+            .saturating_sub(INDICATOR_WIDTH * 2 + self.ruler_size.0) / SQUARE_WIDTH;
+        // 上のバー、盤面の上下の空行、下のバー、列の名前
+        let height = self.terminal_height
+            .saturating_sub(self.top_bar_rows() + 3 + self.ruler_size.1);
+
+        Some((width.max(1), height.max(1)))
+    }
+
+    // set_boardで渡す盤面に付いている列の名前と行の番号の(列数, 行数)
+    // 付いていなければ(0, 0)
+    pub fn set_ruler_size(&mut self, ruler_size: (usize, usize)) {
+        self.ruler_size = ruler_size;
+    }
+
+    // 次のset_boardから使う
+    pub fn set_scroll_indicators(&mut self, scroll_indicators: ScrollIndicators) {
+        self.scroll_indicators = scroll_indicators;
     }
 
     pub fn set_board(&mut self, board_buffer: String) {
         self.board_buffer.clear();
+        // 盤面は上のバーと空行の下から始まる
+        let top = self.top_bar_rows() + 1;
+        self.board_layout = BoardLayout::new(&board_buffer, self.terminal_width, top,
+                                             self.ruler_size);
 
         if self.terminal_width == 0 {
             self.board_buffer = board_buffer
         } else {
-            for line in board_buffer.lines() {
-                //let count = count_color_escape_sequences(line);
-                //let count_zenkaku = count_zenkaku_number(line);
-                //let width = self.terminal_width + count - count_zenkaku;
-                let count= count_color_escape_sequences_and_zenkaku_number(line);
-                let width = self.terminal_width + count.0 - count.1;
-                self.board_buffer += &format!("{:^width$}\n", line, width = width);
+            let left = self.board_layout.left;
+            // 左右の印はマスの真ん中の行にだけ出す
+            let ruler_rows = self.ruler_size.1;
+            let middle = ruler_rows + self.board_layout.height.saturating_sub(ruler_rows) / 2;
+            for (i, line) in board_buffer.lines().enumerate() {
+                let is_middle = i == middle && INDICATOR_WIDTH <= left;
+                if is_middle && self.scroll_indicators.left {
+                    self.board_buffer += &" ".repeat(left - INDICATOR_WIDTH);
+                    self.board_buffer += "< ";
+                } else {
+                    self.board_buffer += &" ".repeat(left);
+                }
+                self.board_buffer += line;
+                if is_middle && self.scroll_indicators.right {
+                    self.board_buffer += " >";
+                }
+                self.board_buffer += "\n";
             }
         }
     }
 
+    // 上のバーが端末の幅で折り返されて何行になるか
+    fn top_bar_rows(&self) -> usize {
+        if self.terminal_width == 0 {
+            return 1;
+        }
+        let width = text_width(self.top_bar_buffer.trim_end());
+        ((width + self.terminal_width - 1) / self.terminal_width).max(1)
+    }
+
     pub fn set_top_bar(&mut self, top_bar_buffer: String) {
         self.top_bar_buffer.clear();
 
         if self.terminal_width == 0 {
             self.top_bar_buffer = top_bar_buffer;
         } else {
-            let count = count_color_escape_sequences(&top_bar_buffer);
-            let width = self.terminal_width + count;
+            let width = centering_width(&top_bar_buffer, self.terminal_width);
             self.top_bar_buffer = format!("{:^width$}", top_bar_buffer, width = width);
         }
     }
-}
-
-impl Drop for Screen {
-    fn drop(&mut self) {
-        self.termios.set_initial_mode();
-        if self.is_debug == false {
-            print!(alternate_screen!(disable));
-        }
-    }
-}
 
-fn count_color_escape_sequences(text: &str) -> usize {
-    let mut count = 0;
-    let mut is_count = false;
+    pub fn set_bottom_bar(&mut self, bottom_bar_buffer: String) {
+        self.bottom_bar_buffer.clear();
 
-    for c in text.chars() {
-        match c {
-            '\x1b' => {
-                count += 1;
-                is_count = true;
-            }
-            'm' => {
-                count += 1;
-                is_count = false;
-            }
-            _ => {
-                if is_count {
-                    count += 1;
-                }
-            }
+        if self.terminal_width == 0 {
+            self.bottom_bar_buffer = bottom_bar_buffer;
+        } else {
+            let width = centering_width(&bottom_bar_buffer, self.terminal_width);
+            self.bottom_bar_buffer = format!("{:^width$}", bottom_bar_buffer, width = width);
         }
     }
-    count
 }
 
-fn count_zenkaku_number(text: &str) -> usize {
-    let mut count = 0;
-    for c in text.chars() {
-        match c {
-            '１' | '２' | '３' | '４' | '５' |
-            '６' | '７' | '８' | '９' | '０' => {
-                count += 1;
-            },
-            _ => ()
-
-        }
-    }
-    count
+// {:^width$}は文字数で寄せるので、textを端末の幅の中央に寄せるためのwidth
+fn centering_width(text: &str, terminal_width: usize) -> usize {
+    (terminal_width + text.chars().count()).saturating_sub(text_width(text))
 }
 
-fn count_color_escape_sequences_and_zenkaku_number(text: &str) -> (usize, usize) {
-    let mut color_escape_sequence_count = 0;
-    let mut zenkaku_count = 0;
-    let mut is_count = false;
+// 色のエスケープシーケンスを除いた文字だけ
+pub fn strip_color_escape_sequences(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut is_escape = false;
 
     for c in text.chars() {
         match c {
-            '１' | '２' | '３' | '４' | '５' |
-            '６' | '７' | '８' | '９' | '０' => {
-                zenkaku_count += 1;
-            },
-            _ => ()
-        }
-
-        match c {
-            '\x1b' => {
-                color_escape_sequence_count += 1;
-                is_count = true;
-            }
-            'm' => {
-                color_escape_sequence_count += 1;
-                is_count = false;
-            },
+            '\x1b' => is_escape = true,
+            'm' if is_escape => is_escape = false,
             _ => {
-                if is_count {
-                    color_escape_sequence_count += 1;
+                if !is_escape {
+                    stripped.push(c);
                 }
             }
         }
     }
-    
-    (color_escape_sequence_count, zenkaku_count)
+    stripped
 }
 
 #[test]
-fn test_count_color_escape_sequences() {
-    let text = "aiueo";
-    assert_eq!(count_color_escape_sequences(text), 0);
-    let text = "\x1b[2maaa";
-    assert_eq!(count_color_escape_sequences(text), 4);
-    let text = "\x1b[100maaa";
-    assert_eq!(count_color_escape_sequences(text), 6);
-    let text = "\x1b[100maaa\x1b[100m\x1b[100m";
-    assert_eq!(count_color_escape_sequences(text), 18);
+fn test_strip_color_escape_sequences() {
+    assert_eq!(strip_color_escape_sequences("aiueo"), "aiueo");
+    assert_eq!(strip_color_escape_sequences("\x1b[7m[]\x1b[27m\x1b[93m/>\x1b[0mm"), "[]/>m");
 }
 #[test]
-fn test_count_zenkaku_number() {
-    assert_eq!(count_zenkaku_number("123"), 0);
-    assert_eq!(count_zenkaku_number("１２３"), 3);
-    assert_eq!(count_zenkaku_number("１２３123"), 3);
-    assert_eq!(count_zenkaku_number("１２３123１２３"), 6);
+fn test_board_layout() {
+    // 幅6の盤面を幅21の端末の中央に置くと、左に7列空く
+    let board = "[][]\x1b[7m[]\x1b[27m\n１\x1b[93m/>\x1b[0m  ";
+    let layout = BoardLayout::new(board, 21, 2, (0, 0));
+    assert_eq!(layout, BoardLayout { top: 2, left: 7, width: 6, height: 2, ruler_size: (0, 0) });
+    let mut screen_board = String::new();
+    for line in board.lines() {
+        screen_board += &format!("{:^width$}\n", line, width = centering_width(line, 21));
+    }
+    assert!(screen_board.starts_with("       []"));
+
+    assert_eq!(layout.square_at(6, 2), None);
+    assert_eq!(layout.square_at(7, 2), Some((0, 0)));
+    assert_eq!(layout.square_at(8, 2), Some((0, 0)));
+    assert_eq!(layout.square_at(9, 3), Some((1, 1)));
+    assert_eq!(layout.square_at(12, 3), Some((2, 1)));
+    assert_eq!(layout.square_at(13, 3), None);
+    assert_eq!(layout.square_at(7, 1), None);
+    assert_eq!(layout.square_at(7, 4), None);
+
+    // 端末の幅が分からなければ寄せない
+    let layout = BoardLayout::new(board, 0, 2, (0, 0));
+    assert_eq!(layout.square_at(0, 2), Some((0, 0)));
+
+    // 上のバーが折り返されたら、その分だけ下にずれる
+    let layout = BoardLayout::new(board, 21, 3, (0, 0));
+    assert_eq!(layout.square_at(7, 2), None);
+    assert_eq!(layout.square_at(7, 3), Some((0, 0)));
+
+    // 列の名前と行の番号を含めて中央に寄せ、マスの位置はその分ずらす
+    let board = "   A B\n 1 [][]\n 2 [][]";
+    let layout = BoardLayout::new(board, 21, 2, (3, 1));
+    assert_eq!(layout.left, 7);
+    assert_eq!(layout.square_at(9, 3), None);
+    assert_eq!(layout.square_at(10, 2), None);
+    assert_eq!(layout.square_at(10, 3), Some((0, 0)));
+    assert_eq!(layout.square_at(13, 4), Some((1, 1)));
+    assert_eq!(layout.square_at(14, 4), None);
+    assert_eq!(layout.square_at(13, 5), None);
 }
-#[test]
-fn test_count_color_escape_sequences_and_zenkaku_number() {
-    let text = "";
-    assert_eq!(count_color_escape_sequences_and_zenkaku_number(text), (0, 0));
-    let text = "\x1b[100maaa\x1b[100m\x1b[100m";
-    assert_eq!(count_color_escape_sequences_and_zenkaku_number(text),
-               (count_color_escape_sequences(text),
-                0));
-    let text = "１２３123１２３";
-    assert_eq!(count_color_escape_sequences_and_zenkaku_number(text),
-               (0,
-                count_zenkaku_number(text)));
-    let text = "\x1b[100maaa１２３123１２３\x1b[100maaa\x1b[100m";
-    assert_eq!(count_color_escape_sequences_and_zenkaku_number(text),
-               (count_color_escape_sequences(text),
-                count_zenkaku_number(text)));
-}
\ No newline at end of file
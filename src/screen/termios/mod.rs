@@ -1,10 +1,10 @@
 extern crate libc;
-use std::default::Default;
-use std::os::raw::{c_int, c_uchar, c_uint};
+use std::fmt;
+use std::io;
+use std::os::raw::c_uint;
+use std::sync::OnceLock;
 
-type cc_t = c_uchar;
-type speed_t = c_uint;
-type tcflag_t = c_uint;
+type TcflagT = c_uint;
 
 fn termios_new() -> libc::termios {
     libc::termios {
@@ -24,7 +24,7 @@ fn termios_new() -> libc::termios {
 fn test_termios_new() {
     let mut mode: libc::termios = termios_new();
     unsafe {
-        let mut ptr = &mut mode;
+        let ptr = &mut mode;
         let ret = libc::tcgetattr(0, ptr);
         if ret != 0 {
             panic!();
@@ -32,63 +32,116 @@ fn test_termios_new() {
     }
 }
 
+// 端末の設定を読み書きできなかったときのエラー
+#[derive(Debug)]
+pub enum TermiosError {
+    // 標準入力が端末ではない
+    NotATerminal,
+    // tcgetattrかtcsetattrが失敗した
+    Io(io::Error),
+}
+
+impl TermiosError {
+    // 直前に失敗したlibcの関数のerrnoから作る
+    fn last_os_error() -> TermiosError {
+        let error = io::Error::last_os_error();
+        match error.raw_os_error() {
+            Some(libc::ENOTTY) => TermiosError::NotATerminal,
+            _ => TermiosError::Io(error)
+        }
+    }
+}
+
+impl fmt::Display for TermiosError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TermiosError::NotATerminal => write!(f, "stdin is not a terminal"),
+            TermiosError::Io(e) => write!(f, "cannot configure the terminal: {}", e),
+        }
+    }
+}
+
+// 最初にTermiosを作ったときの端末の設定
+// シグナルハンドラやpanicのフックからも戻せるようにstaticに置く
+static INITIAL_MODE: OnceLock<libc::termios> = OnceLock::new();
+
+// 端末を最初の設定に戻す。シグナルハンドラからも呼べるようにpanicしない
+pub fn restore_initial_mode() {
+    if let Some(mode) = INITIAL_MODE.get() {
+        unsafe { libc::tcsetattr(0, libc::TCSANOW, mode) };
+    }
+}
+
 pub struct Termios {
     mode: libc::termios,
     initial_mode: libc::termios,
 }
 
 impl Termios {
-    pub fn new() -> Termios {
+    pub fn new() -> Result<Termios, TermiosError> {
         let mut mode = termios_new();
         
-        unsafe {
-            let result = libc::tcgetattr(0, &mut mode);
-            if result != 0 {
-                panic!();
-            }
+        let result = unsafe { libc::tcgetattr(0, &mut mode) };
+        if result != 0 {
+            return Err(TermiosError::last_os_error());
         }
 
-        Termios {
-            mode: mode,
-            initial_mode: mode.clone(),
-        }
+        Ok(Termios {
+            mode,
+            initial_mode: *INITIAL_MODE.get_or_init(|| mode),
+        })
     }
 
-    pub fn set_initial_mode(&self) -> &Termios {
-        unsafe {
-            let result = libc::tcsetattr(0, libc::TCSANOW, &self.initial_mode);
-            if result != 0 {
-                panic!();
-            }
+    pub fn set_initial_mode(&self) -> Result<&Termios, TermiosError> {
+        let result = unsafe { libc::tcsetattr(0, libc::TCSANOW, &self.initial_mode) };
+        if result != 0 {
+            return Err(TermiosError::last_os_error());
         }
-        self
+        Ok(self)
     }
 
-    pub fn set_mode(&self) -> &Termios {
-        unsafe {
-            let result = libc::tcsetattr(0, libc::TCSANOW, &self.mode);
-            if result != 0 {
-                panic!();
-            }
+    pub fn set_mode(&self) -> Result<&Termios, TermiosError> {
+        let result = unsafe { libc::tcsetattr(0, libc::TCSANOW, &self.mode) };
+        if result != 0 {
+            return Err(TermiosError::last_os_error());
         }
-        self
+        Ok(self)
     }
 
-    pub fn mode_on(&mut self, flag: tcflag_t) -> &Termios {
+    pub fn mode_on(&mut self, flag: TcflagT) -> Result<&Termios, TermiosError> {
         self.mode.c_lflag |= flag;
         self.set_mode()
     }
 
-    pub fn mode_off(&mut self, flag: tcflag_t) -> &Termios {
+    pub fn mode_off(&mut self, flag: TcflagT) -> Result<&Termios, TermiosError> {
         self.mode.c_lflag &= !flag;
         self.set_mode()
     }
+
+    // 非カノニカルモードでreadが返るまでの条件
+    // vmin: 最低限読む文字数、vtime: 待つ時間 (0.1秒単位)
+    // どちらも0ならreadはすぐに返る
+    pub fn set_read_timeout(&mut self, vmin: u8, vtime: u8) -> Result<&Termios, TermiosError> {
+        self.mode.c_cc[libc::VMIN] = vmin;
+        self.mode.c_cc[libc::VTIME] = vtime;
+        self.set_mode()
+    }
 }
 
 #[test]
 #[ignore]
 fn test_termios() {
-    let mut termios = Termios::new();
-    termios.mode_on(libc::ECHO);
-    termios.set_initial_mode();
+    let mut termios = Termios::new().unwrap();
+    termios.mode_on(libc::ECHO).unwrap();
+    termios.set_initial_mode().unwrap();
+}
+#[test]
+#[ignore]
+fn test_termios_set_read_timeout() {
+    let mut termios = Termios::new().unwrap();
+    termios.mode_off(libc::ICANON).unwrap();
+    termios.set_read_timeout(0, 1).unwrap();
+    assert_eq!(termios.mode.c_cc[libc::VMIN], 0);
+    assert_eq!(termios.mode.c_cc[libc::VTIME], 1);
+    termios.set_initial_mode().unwrap();
 }
\ No newline at end of file
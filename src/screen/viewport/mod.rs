@@ -0,0 +1,173 @@
+#![allow(dead_code)]
+
+// 見えていない部分がどちらにあるか
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct ScrollIndicators {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+// 盤面のうち画面に表示する範囲
+// 位置はマス単位の(x, y)、大きさは(列数, 行数)
+#[derive(Debug, PartialEq)]
+pub struct Viewport {
+    origin: (usize, usize),
+    size: (usize, usize),
+    board_size: (usize, usize),
+}
+
+impl Viewport {
+    // board_sizeは(height, width)
+    pub fn new(board_size: (usize, usize)) -> Viewport {
+        let board_size = (board_size.1, board_size.0);
+        Viewport {
+            origin: (0, 0),
+            size: board_size,
+            board_size,
+        }
+    }
+
+    pub fn get_origin(&self) -> (usize, usize) {
+        self.origin
+    }
+
+    pub fn get_size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    // 表示できるマスの数が変わったときに呼ぶ。盤面より大きければ盤面の大きさにする
+    pub fn resize(&mut self, size: (usize, usize)) {
+        self.size = (size.0.clamp(1, self.board_size.0), size.1.clamp(1, self.board_size.1));
+        self.origin = self.clamp_origin(self.origin);
+    }
+
+    fn clamp_origin(&self, origin: (usize, usize)) -> (usize, usize) {
+        (origin.0.min(self.board_size.0 - self.size.0),
+         origin.1.min(self.board_size.1 - self.size.1))
+    }
+
+    // カーソルが見えるように、必要な分だけずらす
+    pub fn follow(&mut self, cursor: (usize, usize)) {
+        let mut origin = self.origin;
+        if cursor.0 < origin.0 {
+            origin.0 = cursor.0;
+        } else if origin.0 + self.size.0 <= cursor.0 {
+            origin.0 = cursor.0 + 1 - self.size.0;
+        }
+        if cursor.1 < origin.1 {
+            origin.1 = cursor.1;
+        } else if origin.1 + self.size.1 <= cursor.1 {
+            origin.1 = cursor.1 + 1 - self.size.1;
+        }
+        self.origin = self.clamp_origin(origin);
+    }
+
+    // １画面分ずらして、カーソルも同じだけ動かした位置を返す
+    // 端でそれ以上ずらせなければ、カーソルを端まで動かす
+    pub fn page(&mut self, direction: Direction, cursor: (usize, usize)) -> (usize, usize) {
+        let (origin, size) = (self.origin, self.size);
+        let last = (self.board_size.0 - 1, self.board_size.1 - 1);
+        let (new_origin, new_cursor) = match direction {
+            Direction::Left if origin.0 == 0 => (origin, (0, cursor.1)),
+            Direction::Right if origin.0 + size.0 > last.0 => (origin, (last.0, cursor.1)),
+            Direction::Up if origin.1 == 0 => (origin, (cursor.0, 0)),
+            Direction::Down if origin.1 + size.1 > last.1 => (origin, (cursor.0, last.1)),
+            Direction::Left => {
+                let x = origin.0.saturating_sub(size.0);
+                ((x, origin.1), (cursor.0 - (origin.0 - x), cursor.1))
+            },
+            Direction::Right => {
+                let x = (origin.0 + size.0).min(self.board_size.0 - size.0);
+                ((x, origin.1), ((cursor.0 + (x - origin.0)).min(last.0), cursor.1))
+            },
+            Direction::Up => {
+                let y = origin.1.saturating_sub(size.1);
+                ((origin.0, y), (cursor.0, cursor.1 - (origin.1 - y)))
+            },
+            Direction::Down => {
+                let y = (origin.1 + size.1).min(self.board_size.1 - size.1);
+                ((origin.0, y), (cursor.0, (cursor.1 + (y - origin.1)).min(last.1)))
+            }
+        };
+        self.origin = new_origin;
+        new_cursor
+    }
+
+    pub fn get_scroll_indicators(&self) -> ScrollIndicators {
+        ScrollIndicators {
+            up: 0 < self.origin.1,
+            down: self.origin.1 + self.size.1 < self.board_size.1,
+            left: 0 < self.origin.0,
+            right: self.origin.0 + self.size.0 < self.board_size.0,
+        }
+    }
+}
+
+#[test]
+fn test_viewport_follow() {
+    let mut viewport = Viewport::new((20, 30));
+    assert_eq!(viewport.get_size(), (30, 20));
+    viewport.resize((10, 5));
+    assert_eq!(viewport.get_size(), (10, 5));
+
+    viewport.follow((9, 4));
+    assert_eq!(viewport.get_origin(), (0, 0));
+    viewport.follow((10, 5));
+    assert_eq!(viewport.get_origin(), (1, 1));
+    viewport.follow((29, 19));
+    assert_eq!(viewport.get_origin(), (20, 15));
+    viewport.follow((25, 17));
+    assert_eq!(viewport.get_origin(), (20, 15));
+    viewport.follow((3, 2));
+    assert_eq!(viewport.get_origin(), (3, 2));
+
+    // 大きくしたら盤面からはみ出さないように戻す
+    viewport.follow((29, 19));
+    viewport.resize((100, 100));
+    assert_eq!(viewport.get_size(), (30, 20));
+    assert_eq!(viewport.get_origin(), (0, 0));
+}
+#[test]
+fn test_viewport_page() {
+    let mut viewport = Viewport::new((20, 25));
+    viewport.resize((10, 8));
+
+    assert_eq!(viewport.page(Direction::Right, (3, 2)), (13, 2));
+    assert_eq!(viewport.get_origin(), (10, 0));
+    // 最後のページは盤面の端にそろえる
+    assert_eq!(viewport.page(Direction::Right, (13, 2)), (18, 2));
+    assert_eq!(viewport.get_origin(), (15, 0));
+    assert_eq!(viewport.page(Direction::Right, (18, 2)), (24, 2));
+    assert_eq!(viewport.get_origin(), (15, 0));
+    assert_eq!(viewport.page(Direction::Left, (24, 2)), (14, 2));
+    assert_eq!(viewport.get_origin(), (5, 0));
+
+    assert_eq!(viewport.page(Direction::Up, (14, 2)), (14, 0));
+    assert_eq!(viewport.page(Direction::Down, (14, 0)), (14, 8));
+    assert_eq!(viewport.get_origin(), (5, 8));
+    assert_eq!(viewport.page(Direction::Down, (14, 15)), (14, 19));
+    assert_eq!(viewport.get_origin(), (5, 12));
+}
+#[test]
+fn test_viewport_scroll_indicators() {
+    let mut viewport = Viewport::new((5, 5));
+    assert_eq!(viewport.get_scroll_indicators(), ScrollIndicators::default());
+
+    viewport.resize((3, 3));
+    viewport.follow((3, 0));
+    assert_eq!(viewport.get_scroll_indicators(),
+               ScrollIndicators { up: false, down: true, left: true, right: true });
+    viewport.follow((4, 4));
+    assert_eq!(viewport.get_scroll_indicators(),
+               ScrollIndicators { up: true, down: false, left: true, right: false });
+}
@@ -0,0 +1,390 @@
+#![allow(dead_code)]
+
+use crate::screen::frame::{char_width, Cell, Style};
+
+// 画面に出力されたエスケープシーケンスを解釈して、見えている画面を再現する
+// escape_sequenceのマクロが出力するものと、改行などの制御文字だけを扱う
+#[derive(Debug, Clone)]
+pub struct Vt {
+    height: usize,
+    width: usize,
+    // 通常の画面と代替画面
+    main_cells: Vec<Vec<Cell>>,
+    alternate_cells: Vec<Vec<Cell>>,
+    is_alternate_screen: bool,
+    // 代替画面に切り替えたときのカーソルの位置
+    saved_cursor: (usize, usize),
+    // (行, 列)。列がwidthなら次の文字で折り返す
+    cursor: (usize, usize),
+    style: Style,
+    is_cursor_visible: bool,
+    is_mouse_tracking: bool,
+    // 途中までしか来ていないエスケープシーケンス
+    pending: String,
+}
+
+impl Vt {
+    pub fn new(height: usize, width: usize) -> Vt {
+        Vt {
+            height,
+            width,
+            main_cells: blank_cells(height, width),
+            alternate_cells: blank_cells(height, width),
+            is_alternate_screen: false,
+            saved_cursor: (0, 0),
+            cursor: (0, 0),
+            style: Style::default(),
+            is_cursor_visible: true,
+            is_mouse_tracking: false,
+            pending: String::new(),
+        }
+    }
+
+    pub fn get_size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    // はみ出した部分は捨て、増えた部分は空白にする
+    pub fn resize(&mut self, height: usize, width: usize) {
+        for cells in [&mut self.main_cells, &mut self.alternate_cells].iter_mut() {
+            cells.resize(height, vec![Cell::default(); width]);
+            for line in cells.iter_mut() {
+                line.resize(width, Cell::default());
+            }
+        }
+        self.height = height;
+        self.width = width;
+        self.cursor = (self.cursor.0.min(height.saturating_sub(1)), self.cursor.1.min(width));
+    }
+
+    pub fn get_cursor(&self) -> (usize, usize) {
+        (self.cursor.0, self.cursor.1.min(self.width.saturating_sub(1)))
+    }
+
+    pub fn is_alternate_screen(&self) -> bool {
+        self.is_alternate_screen
+    }
+
+    pub fn is_cursor_visible(&self) -> bool {
+        self.is_cursor_visible
+    }
+
+    pub fn is_mouse_tracking(&self) -> bool {
+        self.is_mouse_tracking
+    }
+
+    pub fn get_cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        self.cells().get(row)?.get(col)
+    }
+
+    fn cells(&self) -> &Vec<Vec<Cell>> {
+        if self.is_alternate_screen { &self.alternate_cells } else { &self.main_cells }
+    }
+
+    fn cells_mut(&mut self) -> &mut Vec<Vec<Cell>> {
+        if self.is_alternate_screen { &mut self.alternate_cells } else { &mut self.main_cells }
+    }
+
+    pub fn feed(&mut self, output: &str) {
+        let mut text = std::mem::take(&mut self.pending);
+        text += output;
+        let mut chars = text.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\x1b' => {
+                    // シーケンスが途中で切れていたら、ESCから後を次のfeedまで取っておく
+                    match chars.peek() {
+                        None => {
+                            self.pending = text[i..].to_string();
+                            return;
+                        },
+                        Some((_, '[')) => {
+                            chars.next();
+                            let mut params = String::new();
+                            let mut final_char = None;
+                            for (_, c) in chars.by_ref() {
+                                if ('\x40'..='\x7e').contains(&c) {
+                                    final_char = Some(c);
+                                    break;
+                                }
+                                params.push(c);
+                            }
+                            match final_char {
+                                Some(final_char) => self.csi(&params, final_char),
+                                None => {
+                                    self.pending = text[i..].to_string();
+                                    return;
+                                }
+                            }
+                        },
+                        Some(_) => {
+                            chars.next();
+                        }
+                    }
+                },
+                '\n' => self.line_feed(),
+                '\r' => self.cursor.1 = 0,
+                '\x08' => self.cursor.1 = self.cursor.1.min(self.width).saturating_sub(1),
+                c => self.put_char(c)
+            }
+        }
+    }
+
+    // 端末のONLCRと同じように、行の先頭にも戻る
+    fn line_feed(&mut self) {
+        self.cursor.1 = 0;
+        if self.cursor.0 + 1 < self.height {
+            self.cursor.0 += 1;
+        } else {
+            // 一番下なら上にずらす
+            let width = self.width;
+            let cells = self.cells_mut();
+            if !cells.is_empty() {
+                cells.remove(0);
+                cells.push(vec![Cell::default(); width]);
+            }
+        }
+    }
+
+    fn put_char(&mut self, c: char) {
+        let char_width = char_width(c);
+        if char_width == 0 || self.height == 0 || self.width < char_width {
+            return;
+        }
+        if self.width < self.cursor.1 + char_width {
+            self.line_feed();
+        }
+
+        let (row, col) = self.cursor;
+        let style = self.style.clone();
+        let cells = self.cells_mut();
+        // 全角の文字の片側だけを書き換えたら、もう片側は空白になる
+        if cells[row][col].width == 0 && 0 < col {
+            cells[row][col - 1] = Cell::default();
+        }
+        for i in col..col + char_width {
+            if cells[row][i].width == 2 && i + 1 < cells[row].len() {
+                cells[row][i + 1] = Cell::default();
+            }
+        }
+        cells[row][col] = Cell { ch: c, style: style.clone(), width: char_width };
+        for i in 1..char_width {
+            cells[row][col + i] = Cell { ch: ' ', style: style.clone(), width: 0 };
+        }
+        self.cursor.1 += char_width;
+    }
+
+    fn csi(&mut self, params: &str, final_char: char) {
+        if let Some(params) = params.strip_prefix('?') {
+            let is_enabled = match final_char {
+                'h' => true,
+                'l' => false,
+                _ => return
+            };
+            for param in params.split(';') {
+                match param {
+                    "1049" => self.set_alternate_screen(is_enabled),
+                    "25" => self.is_cursor_visible = is_enabled,
+                    "1000" => self.is_mouse_tracking = is_enabled,
+                    _ => ()
+                }
+            }
+            return;
+        }
+
+        let numbers: Vec<usize> = params.split(';').map(|n| n.parse().unwrap_or(0)).collect();
+        // 省略されたか0なら1
+        let n = |i: usize| numbers.get(i).cloned().filter(|&n| n != 0).unwrap_or(1);
+        let last_row = self.height.saturating_sub(1);
+        let last_col = self.width.saturating_sub(1);
+        match final_char {
+            'm' => self.style.apply_sgr(params),
+            'H' | 'f' => self.cursor = ((n(0) - 1).min(last_row), (n(1) - 1).min(last_col)),
+            'A' => self.cursor.0 = self.cursor.0.saturating_sub(n(0)),
+            'B' => self.cursor.0 = (self.cursor.0 + n(0)).min(last_row),
+            'C' => self.cursor.1 = (self.cursor.1 + n(0)).min(last_col),
+            'D' => self.cursor.1 = self.cursor.1.min(last_col).saturating_sub(n(0)),
+            'J' => {
+                let (row, col) = self.cursor;
+                let width = self.width;
+                let cells = self.cells_mut();
+                match numbers[0] {
+                    0 => {
+                        clear_line(&mut cells[row], col, width);
+                        for line in cells.iter_mut().skip(row + 1) {
+                            clear_line(line, 0, width);
+                        }
+                    },
+                    1 => {
+                        for line in cells.iter_mut().take(row) {
+                            clear_line(line, 0, width);
+                        }
+                        clear_line(&mut cells[row][..(col + 1).min(width)], 0, width);
+                    },
+                    _ => {
+                        for line in cells.iter_mut() {
+                            clear_line(line, 0, width);
+                        }
+                    }
+                }
+            },
+            'K' => {
+                let (row, col) = self.cursor;
+                let width = self.width;
+                let line = &mut self.cells_mut()[row];
+                match numbers[0] {
+                    0 => clear_line(line, col, width),
+                    1 => clear_line(&mut line[..(col + 1).min(width)], 0, width),
+                    _ => clear_line(line, 0, width)
+                }
+            },
+            _ => ()
+        }
+    }
+
+    fn set_alternate_screen(&mut self, is_enabled: bool) {
+        if is_enabled == self.is_alternate_screen {
+            return;
+        }
+        if is_enabled {
+            self.saved_cursor = self.cursor;
+            self.alternate_cells = blank_cells(self.height, self.width);
+        } else {
+            self.cursor = self.saved_cursor;
+        }
+        self.is_alternate_screen = is_enabled;
+    }
+
+    // 見た目を除いた各行の文字。行末の空白は除く
+    pub fn to_lines(&self) -> Vec<String> {
+        self.cells().iter()
+            .map(|line| {
+                let line: String = line.iter()
+                    .filter(|cell| cell.width != 0)
+                    .map(|cell| cell.ch)
+                    .collect();
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    // 見た目が変わるところに{SGRのパラメータ}を挟んだ各行
+    // 何も指定されていない見た目は{}。行末の空白は除く
+    // 例: "[]{7}[]{}[]"
+    pub fn to_snapshot(&self) -> Vec<String> {
+        self.cells().iter()
+            .map(|line| {
+                let mut snapshot = String::new();
+                let mut style = Style::default();
+                // 行末の見た目のない空白を除く
+                let end = line.iter()
+                    .rposition(|cell| cell.ch != ' ' || cell.style != Style::default())
+                    .map(|i| i + 1)
+                    .unwrap_or(0);
+                for cell in &line[..end] {
+                    if cell.style != style {
+                        let sgr = cell.style.to_sgr();
+                        let params = sgr[2..sgr.len() - 1].trim_start_matches('0')
+                            .trim_start_matches(';');
+                        snapshot += &format!("{{{}}}", params);
+                        style = cell.style.clone();
+                    }
+                    if cell.width != 0 {
+                        snapshot.push(cell.ch);
+                    }
+                }
+                if style != Style::default() {
+                    snapshot += "{}";
+                }
+                snapshot
+            })
+            .collect()
+    }
+}
+
+fn blank_cells(height: usize, width: usize) -> Vec<Vec<Cell>> {
+    vec![vec![Cell::default(); width]; height]
+}
+
+fn clear_line(line: &mut [Cell], from: usize, width: usize) {
+    for cell in line.iter_mut().take(width).skip(from) {
+        *cell = Cell::default();
+    }
+}
+
+#[test]
+fn test_vt_print() {
+    let mut vt = Vt::new(3, 6);
+    vt.feed("ab\ncd");
+    assert_eq!(vt.to_lines(), vec!["ab", "cd", ""]);
+    assert_eq!(vt.get_cursor(), (1, 2));
+
+    // 折り返す。全角が入らなければ次の行に書く
+    vt.feed("efgh１");
+    assert_eq!(vt.to_lines(), vec!["ab", "cdefgh", "１"]);
+    // 一番下で改行したら上にずれる
+    vt.feed("\nxyz");
+    assert_eq!(vt.to_lines(), vec!["cdefgh", "１", "xyz"]);
+    vt.feed("\r\x08X");
+    assert_eq!(vt.to_lines(), vec!["cdefgh", "１", "Xyz"]);
+}
+#[test]
+fn test_vt_escape_sequences() {
+    let mut vt = Vt::new(3, 6);
+    vt.feed(alternate_screen!(enable));
+    vt.feed(hide_cursor!(enable));
+    vt.feed(mouse_tracking!(enable));
+    assert!(vt.is_alternate_screen() && !vt.is_cursor_visible() && vt.is_mouse_tracking());
+
+    vt.feed(&format!("abcdef{}xy{}zz{}", move_cursor!(2, 3), home_cursor!(), move_cursor!(9, 9)));
+    assert_eq!(vt.to_lines(), vec!["zzcdef", "  xy", ""]);
+    assert_eq!(vt.get_cursor(), (2, 5));
+    vt.feed("\x1b[2;1H\x1b[2C\x1b[K\x1b[A\x1b[1Dq");
+    assert_eq!(vt.to_lines(), vec!["zqcdef", "", ""]);
+
+    vt.feed(clear!());
+    assert_eq!(vt.to_lines(), vec!["", "", ""]);
+
+    // 代替画面から戻ると元の画面とカーソルに戻る
+    vt.feed(alternate_screen!(disable));
+    vt.feed(hide_cursor!(disable));
+    assert!(!vt.is_alternate_screen() && vt.is_cursor_visible());
+    assert_eq!(vt.get_cursor(), (0, 0));
+}
+#[test]
+fn test_vt_split_sequence() {
+    let mut vt = Vt::new(1, 6);
+    vt.feed("a\x1b");
+    assert_eq!(vt.pending, "\x1b");
+    vt.feed("[9");
+    assert_eq!(vt.pending, "\x1b[9");
+    vt.feed("1mb\x1b[0mc");
+    assert_eq!(vt.pending, "");
+    assert_eq!(vt.to_snapshot(), vec!["a{91}b{}c"]);
+    // 切れたシーケンスの前までは書き終えて、残りだけを取っておく
+    let mut vt = Vt::new(1, 6);
+    vt.feed("\x1b[91mあ\x1b[0mい\x1b[");
+    assert_eq!(vt.pending, "\x1b[");
+    vt.feed("91mう");
+    assert_eq!(vt.to_snapshot(), vec!["{91}あ{}い{91}う{}"]);
+}
+#[test]
+fn test_vt_snapshot() {
+    let mut vt = Vt::new(2, 10);
+    vt.feed(&format!("{}[]{}{}",
+                     foreground!(red), foreground!(default), background!(blue)));
+    vt.feed("\x1b[7m１\x1b[27m \x1b[49m\n\x1b[1;93m/>\x1b[0m  ");
+    assert_eq!(vt.to_snapshot(), vec!["{31}[]{7;44}１{44} {}", "{1;93}/>{}"]);
+    assert!(vt.get_cell(0, 2).unwrap().style.reverse);
+    assert_eq!(vt.to_lines(), vec!["[]１", "/>"]);
+}
+#[test]
+fn test_vt_overwrite_wide_char() {
+    let mut vt = Vt::new(1, 6);
+    vt.feed("１２");
+    vt.feed("\x1b[1;2Hx");
+    assert_eq!(vt.to_lines(), vec![" x２"]);
+    vt.feed("\x1b[1;3Hy");
+    assert_eq!(vt.to_lines(), vec![" xy"]);
+}
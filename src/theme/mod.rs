@@ -0,0 +1,177 @@
+#![allow(dead_code)]
+
+use std::fmt;
+
+use crate::screen::frame::char_width;
+
+// マス１つの表示上の幅。どのテーマのグリフもこの幅にそろえる
+pub const GLYPH_WIDTH: usize = 2;
+
+// マスの状態を表す文字と見た目
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glyph {
+    // GLYPH_WIDTH列になるように右を空白で埋めた文字
+    text: String,
+    // SGRのパラメータ。例: "93"
+    style: Option<String>,
+}
+
+impl Glyph {
+    fn new(text: &str, style: &str) -> Glyph {
+        let width: usize = text.chars().map(char_width).sum();
+        Glyph {
+            text: format!("{}{}", text, " ".repeat(GLYPH_WIDTH.saturating_sub(width))),
+            style: if style.is_empty() { None } else { Some(style.to_string()) },
+        }
+    }
+
+    // 見た目を付けず、埋めた空白も除いた文字
+    pub fn get_text(&self) -> &str {
+        self.text.trim_end()
+    }
+
+    // 見た目を付けた、埋めた空白を除いた文字。上のバーなどの文中で使う
+    pub fn to_short_string(&self) -> String {
+        match &self.style {
+            Some(style) => format!("\x1b[{}m{}\x1b[0m", style, self.get_text()),
+            None => self.get_text().to_string()
+        }
+    }
+}
+
+impl fmt::Display for Glyph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.style {
+            Some(style) => write!(f, "\x1b[{}m{}\x1b[0m", style, self.text),
+            None => write!(f, "{}", self.text)
+        }
+    }
+}
+
+// 組み込みのテーマ
+// (名前, [(文字, 見た目)])。閉じたマス、旗、地雷、開いた空のマス、１から８の数字の順
+const PRESETS: [(&str, [(&str, &str); 12]); 4] = [
+    ("full-width", [
+        ("[]", ""), ("/>", "93"), ("<>", "91"), ("  ", ""),
+        ("１", ""), ("２", ""), ("３", ""), ("４", ""),
+        ("５", ""), ("６", ""), ("７", ""), ("８", ""),
+    ]),
+    ("classic", [
+        ("#", ""), ("F", "93"), ("*", "91"), (".", "90"),
+        ("1", "94"), ("2", "32"), ("3", "91"), ("4", "34"),
+        ("5", "31"), ("6", "36"), ("7", "95"), ("8", "90"),
+    ]),
+    ("emoji", [
+        ("🟦", ""), ("🚩", ""), ("💣", ""), ("  ", ""),
+        ("１", "94"), ("２", "32"), ("３", "91"), ("４", "34"),
+        ("５", "31"), ("６", "36"), ("７", "95"), ("８", "90"),
+    ]),
+    ("box-drawing", [
+        ("┌┐", ""), ("├┤", "93"), ("╳╳", "91"), ("  ", ""),
+        ("1", "94"), ("2", "32"), ("3", "91"), ("4", "34"),
+        ("5", "31"), ("6", "36"), ("7", "95"), ("8", "90"),
+    ]),
+];
+
+pub const DEFAULT_THEME: &str = "full-width";
+
+// マスのそれぞれの状態をどう表示するか
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    name: String,
+    unopened: Glyph,
+    flag: Glyph,
+    mine: Glyph,
+    empty: Glyph,
+    // 1から8の数字
+    numbers: Vec<Glyph>,
+}
+
+impl Theme {
+    pub fn preset(name: &str) -> Result<Theme, String> {
+        let glyphs = match PRESETS.iter().find(|(preset, _)| *preset == name) {
+            Some((_, glyphs)) => glyphs,
+            None => return Err(format!("unknown theme '{}' (available: {})",
+                                       name, Theme::preset_names().join(", ")))
+        };
+        let glyphs: Vec<Glyph> = glyphs.iter().map(|(text, style)| Glyph::new(text, style)).collect();
+
+        Ok(Theme {
+            name: name.to_string(),
+            unopened: glyphs[0].clone(),
+            flag: glyphs[1].clone(),
+            mine: glyphs[2].clone(),
+            empty: glyphs[3].clone(),
+            numbers: glyphs[4..].to_vec(),
+        })
+    }
+
+    pub fn preset_names() -> Vec<&'static str> {
+        PRESETS.iter().map(|(name, _)| *name).collect()
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_unopened(&self) -> &Glyph {
+        &self.unopened
+    }
+
+    pub fn get_flag(&self) -> &Glyph {
+        &self.flag
+    }
+
+    pub fn get_mine(&self) -> &Glyph {
+        &self.mine
+    }
+
+    // 周りに地雷のない開いたマス
+    pub fn get_empty(&self) -> &Glyph {
+        &self.empty
+    }
+
+    // 周りの地雷の数。0なら空のマス
+    pub fn get_number(&self, number: usize) -> &Glyph {
+        match number {
+            1..=8 => &self.numbers[number - 1],
+            _ => &self.empty
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::preset(DEFAULT_THEME).unwrap()
+    }
+}
+
+#[test]
+fn test_theme_presets() {
+    for name in Theme::preset_names() {
+        let theme = Theme::preset(name).unwrap();
+        assert_eq!(theme.get_name(), name);
+        // どのグリフも２列になる
+        let mut glyphs = vec![theme.get_unopened(), theme.get_flag(), theme.get_mine(),
+                              theme.get_empty()];
+        glyphs.extend((1..=8).map(|n| theme.get_number(n)));
+        for glyph in glyphs {
+            let width: usize = glyph.text.chars().map(char_width).sum();
+            assert_eq!(width, GLYPH_WIDTH, "{} {:?}", name, glyph);
+        }
+    }
+    assert!(Theme::preset("unknown").is_err());
+}
+#[test]
+fn test_glyph() {
+    let theme = Theme::default();
+    assert_eq!(theme.get_flag().to_string(), "\x1b[93m/>\x1b[0m");
+    assert_eq!(theme.get_number(3).to_string(), "３");
+    assert_eq!(theme.get_number(0), theme.get_empty());
+
+    let theme = Theme::preset("classic").unwrap();
+    assert_eq!(theme.get_unopened().to_string(), "# ");
+    assert_eq!(theme.get_number(1).to_string(), "\x1b[94m1 \x1b[0m");
+    assert_eq!(theme.get_flag().get_text(), "F");
+    assert_eq!(theme.get_flag().to_short_string(), "\x1b[93mF\x1b[0m");
+}